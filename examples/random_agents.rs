@@ -0,0 +1,41 @@
+//! Drives every ant of the first colony with random actions through the
+//! reinforcement-learning environment and prints the reward per episode.
+//!
+//!     cargo run --release --example random_agents [CONFIG]
+
+use rand::{Rng, SeedableRng};
+
+use ant_simulation::config::Config;
+use ant_simulation::env::{Action, Agents, Env};
+
+fn main() {
+    let config = match std::env::args().nth(1) {
+        Some(path) => Config::load(&path).unwrap(),
+        None => Config::default(),
+    };
+    let layers = config.pheromones.len();
+    let mut env = Env::new(config, 0, Agents::Multi, 600).unwrap();
+    let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+
+    for episode in 0..3 {
+        let mut observations = env.reset(episode).unwrap();
+        let mut total = 0.0;
+        loop {
+            let actions: Vec<Action> = observations.iter()
+                .map(|_| Action {
+                    turn: rng.gen_range(-0.3..0.3),
+                    pick_up: true,
+                    drop: true,
+                    deposit: Some(rng.gen_range(0..layers)),
+                })
+                .collect();
+            let step = env.step(&actions).unwrap();
+            total += step.rewards.iter().sum::<f32>();
+            observations = step.observations;
+            if step.done {
+                break;
+            }
+        }
+        println!("episode {}: {} pellets delivered in {} ticks", episode, total, env.world.tick);
+    }
+}
@@ -1,163 +1,205 @@
 use ggez::nalgebra as na;
-use rand::{Rng, SeedableRng};
-use rand::rngs::StdRng;
 use std::f32;
 use std::f32::consts::PI;
 use rayon::prelude::*;
 
 use crate::utils;
-use crate::TOTAL_ANTS;
+use crate::boundary::BoundaryMode;
+use crate::caste::Traits;
+use crate::config::EnergyConfig;
+use crate::terrain::Terrain;
 
-#[derive(Copy, Clone)]
 pub struct Ant {
-    max_speed : [f32; TOTAL_ANTS],
-    steer_strength: f32,
-    wander_strength: f32,
-    rust_factor: f32,
-
-    pub angle: [f32; TOTAL_ANTS],
-    pub position: [na::Point2<f32>; TOTAL_ANTS],
-    pub velocity: [na::Vector2<f32>; TOTAL_ANTS],
-    pub  desired_direction: [na::Vector2<f32>; TOTAL_ANTS],
-
-    home_pheromones_direction: [Option<na::Vector2<f32>>; TOTAL_ANTS],
-    food_pheromones_direction: [Option<na::Vector2<f32>>; TOTAL_ANTS],
-    
-    pub state: [u32; TOTAL_ANTS],
-
-    pub target_food_position: [Option<na::Point2<f32>>; TOTAL_ANTS],
-    pub target_food_index: [Option<u32>; TOTAL_ANTS],
+    /// Unique per ant for the whole run, unlike the index which changes when
+    /// ants die.
+    pub id: Vec<u64>,
+    next_id: u64,
+    max_speed : Vec<f32>,
+    steer_strength: Vec<f32>,
+    pub wander_strength: Vec<f32>,
+    pub vision: Vec<f32>,
+
+    pub angle: Vec<f32>,
+    pub position: Vec<na::Point2<f32>>,
+    pub velocity: Vec<na::Vector2<f32>>,
+    pub  desired_direction: Vec<na::Vector2<f32>>,
+
+    pub colony: Vec<usize>,
+    /// Index into the configured castes.
+    pub caste: Vec<usize>,
+    pub carried: Vec<u32>,
+    /// Pellets this ant brought home over its life.
+    pub delivered: Vec<u32>,
+    /// Distance walked since the ant last left the nest.
+    pub trip: Vec<f32>,
+    pub energy: Vec<f32>,
+    pub age: Vec<f32>,
+    pub lifespan: Vec<f32>,
+    pub state: Vec<u32>,
+    pub exhausted: Vec<bool>,
+
+    pub target_food_position: Vec<Option<na::Point2<f32>>>,
+    pub target_food_index: Vec<Option<u32>>,
     window_size: (f32, f32),
 }
 
 impl Ant {
-    pub fn initialize(&mut self, init_data: &[(na::Point2<f32>, f32)]) {
-        for i in 0..TOTAL_ANTS {
-            self.angle[i] = init_data[i].1;
-            self.position[i] = init_data[i].0;
-            self.velocity[i] = na::Vector2::new(init_data[i].0.x.cos(), init_data[i].0.y.sin()) * self.max_speed[i];
-            self.desired_direction[i] = na::Vector2::new(init_data[i].1.cos(), init_data[i].1.sin());
-        }
-    }
-
     pub fn new(screen_w: f32, screen_h: f32) -> Self {
-        let new_angle =  [0.0; TOTAL_ANTS];
-        let new_position =  [na::Point2::new(screen_w/2.0, screen_h/2.0); TOTAL_ANTS];
-        let new_velocity =  [na::Vector2::new(1.0 as f32, 0.0 as f32); TOTAL_ANTS];
-        let new_desired_direction =  [na::Vector2::new(1.0 as f32, 0.0 as f32); TOTAL_ANTS];
-        let new_home_pheromones_direction: [Option<na::Vector2<f32>>; TOTAL_ANTS] =  [None; TOTAL_ANTS];
-        let new_food_pheromones_direction: [Option<na::Vector2<f32>>; TOTAL_ANTS] =  [None; TOTAL_ANTS];
-        let new_state: [u32; TOTAL_ANTS] = [0; TOTAL_ANTS];
-        let new_target_food_position: [Option<na::Point2<f32>>; TOTAL_ANTS] =  [None; TOTAL_ANTS];
-        let new_target_food_index: [Option<u32>; TOTAL_ANTS] =  [None; TOTAL_ANTS];
-
         Ant {
-            max_speed: [50.0; TOTAL_ANTS],
-            steer_strength: 100.0,
-            wander_strength: 0.1,
-            rust_factor: 50.0,
-            angle: new_angle,
-            position: new_position,
-            velocity: new_velocity,
-            desired_direction: new_desired_direction,
-            home_pheromones_direction: new_home_pheromones_direction,
-            food_pheromones_direction: new_food_pheromones_direction,
-            state: new_state,
-            target_food_position: new_target_food_position,
-            target_food_index: new_target_food_index,
+            id: Vec::new(),
+            next_id: 0,
+            max_speed: Vec::new(),
+            steer_strength: Vec::new(),
+            wander_strength: Vec::new(),
+            vision: Vec::new(),
+            angle: Vec::new(),
+            position: Vec::new(),
+            velocity: Vec::new(),
+            desired_direction: Vec::new(),
+            colony: Vec::new(),
+            caste: Vec::new(),
+            carried: Vec::new(),
+            delivered: Vec::new(),
+            trip: Vec::new(),
+            energy: Vec::new(),
+            age: Vec::new(),
+            lifespan: Vec::new(),
+            state: Vec::new(),
+            exhausted: Vec::new(),
+            target_food_position: Vec::new(),
+            target_food_index: Vec::new(),
             window_size: (screen_w, screen_h),
         }
     }
 
-    pub fn update(&mut self, dt: f32) {
-        let wander_strength = self.wander_strength;
+    pub fn len(&self) -> usize {
+        self.position.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.position.is_empty()
+    }
+
+    pub fn spawn(&mut self, position: na::Point2<f32>, angle: f32, colony: usize, caste: usize, traits: Traits,
+        energy: f32) {
+        let max_speed = traits.max_speed;
+
+        self.id.push(self.next_id);
+        self.next_id += 1;
+
+        self.max_speed.push(max_speed);
+        self.steer_strength.push(traits.steer_strength);
+        self.wander_strength.push(traits.wander_strength);
+        self.vision.push(traits.vision);
+        self.angle.push(angle);
+        self.position.push(position);
+        self.velocity.push(na::Vector2::new(position.x.cos(), position.y.sin()) * max_speed);
+        self.desired_direction.push(na::Vector2::new(angle.cos(), angle.sin()));
+        self.colony.push(colony);
+        self.caste.push(caste);
+        self.carried.push(0);
+        self.delivered.push(0);
+        self.trip.push(0.0);
+        self.energy.push(energy);
+        self.age.push(0.0);
+        self.lifespan.push(traits.lifespan);
+        self.state.push(0);
+        self.exhausted.push(false);
+        self.target_food_position.push(None);
+        self.target_food_index.push(None);
+    }
+
+    /// Steers every ant towards the direction its brain chose, moves it and
+    /// returns the indices of those that escaped the world and need to be
+    /// respawned. The terrain scales the walking speed and turns ants back at
+    /// impassable ground.
+    pub fn update(&mut self, dt: f32, boundary: BoundaryMode, energy_config: &EnergyConfig,
+        terrain: &Terrain) -> Vec<usize> {
         let window_size = self.window_size;
-        let steer_strength = self.steer_strength;
-        let rust_factor = self.rust_factor;
 
-        self.max_speed.par_iter_mut()
+        let escaped: Vec<bool> = self.max_speed.par_iter_mut()
+            .zip(self.steer_strength.par_iter())
             .zip(self.angle.par_iter_mut())
             .zip(self.position.par_iter_mut())
             .zip(self.velocity.par_iter_mut())
             .zip(self.desired_direction.par_iter_mut())
-            .zip(self.food_pheromones_direction.par_iter_mut())
-            .zip(self.home_pheromones_direction.par_iter_mut())
-            .zip(self.state.par_iter_mut())
-            .zip(self.target_food_position.par_iter_mut())
-            .for_each(|((((((((max_speed, 
+            .zip(self.energy.par_iter_mut())
+            .zip(self.age.par_iter_mut())
+            .zip(self.trip.par_iter_mut())
+            .map(|((((((((max_speed,
+                    steer_strength),
                     angle),
                     position),
                     velocity),
                     desired_direction),
-                    food_pheromones_direction),
-                    home_pheromones_direction),
-                    state),
-                    target_food_position)| {
-
-            let mut rng = StdRng::from_entropy();
-            let random_unit_vector = na::Vector2::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0));
-            if *state == 0 {
-                match food_pheromones_direction {
-                    Some(j) => *desired_direction = *j,
-                    None => {
-                        *desired_direction = *desired_direction + random_unit_vector * wander_strength;
-                        *desired_direction = desired_direction.normalize();
-                    }
-                }
-            } else if *state == 1 {
-                match target_food_position {
-                    Some(j) => {
-                        *desired_direction = na::Vector2::from(*j - *position).normalize();
-                    },
-                    None => {
-                        *desired_direction = *desired_direction + random_unit_vector * wander_strength;
-                        *desired_direction = desired_direction.normalize();
-                    },
-                }
-            } else {
-                let to_home = na::Point2::new(window_size.0, window_size.1)/2.0 - *position;
-                let dist_sq = na::distance_squared(&(na::Point2::new(window_size.0, window_size.1)/2.0), &position);
-                if dist_sq < f32::powi(100.0, 2) {
-                    *desired_direction = na::Vector::from(to_home);
-                } else {
-                    match home_pheromones_direction {
-                        None => {
-                            *desired_direction = *desired_direction + random_unit_vector * wander_strength;
-                            *desired_direction = desired_direction.normalize();
-                        },
-                        Some(j) => {
-                            *desired_direction = *j;
-                        }
-                    }
-                }
-            }
+                    energy),
+                    age),
+                    trip)| {
 
-            let desired_velocity = *desired_direction * *max_speed;
+            let steer_strength = *steer_strength;
+            let max_speed = *max_speed * terrain.speed(position);
+            let desired_velocity = *desired_direction * max_speed;
             let desired_steering_force = (desired_velocity - *velocity) * steer_strength;
             let acceleration = utils::clamp_magnitude(&desired_steering_force, steer_strength);
 
-            let new_velocity = utils::clamp_magnitude(&(*velocity + acceleration * dt),*max_speed);
+            let new_velocity = utils::clamp_magnitude(&(*velocity + acceleration * dt),max_speed);
 
             if !new_velocity.x.is_nan() && !new_velocity.y.is_nan() {
                 *velocity = new_velocity;
             }
 
-            let position_increment = *velocity * dt; // * rust_factor;
-            *position += position_increment;
+            let mut position_increment = *velocity * dt;
+            if terrain.passable(&(*position + position_increment)) {
+                *position += position_increment;
+            } else {
+                position_increment = na::zero();
+                *velocity = -*velocity;
+                *desired_direction = -*desired_direction;
+            }
+
+            *energy -= energy_config.idle_drain * dt + energy_config.movement_drain * position_increment.norm();
+            *age += dt;
+            *trip += position_increment.norm();
+
+            let escaped = boundary.apply(window_size, position, velocity, desired_direction);
 
-            // if position.x > window_size.0 {position.x = 0.0;}
-            // if position.x < 0.0 {position.x = window_size.0;}
-            // if position.y > window_size.1 {position.y = 0.0;}
-            // if position.y < 0.0 {position.y = window_size.1;}
+            // A stopped ant keeps facing the way it was.
+            if velocity.x != 0.0 || velocity.y != 0.0 {
+                *angle = (velocity.y/velocity.x).atan();
+                if velocity[0] < 0.0 {*angle -= PI}
+            }
+            escaped
+        }).collect();
+
+        escaped.iter().enumerate().filter(|(_, escaped)| **escaped).map(|(i, _)| i).collect()
+    }
 
-            if position.x > window_size.0 {*velocity = -*velocity; *desired_direction = -*desired_direction;}
-            if position.x < 0.0 {*velocity = -*velocity; *desired_direction = -*desired_direction;}
-            if position.y > window_size.1 {*velocity = -*velocity; *desired_direction = -*desired_direction;}
-            if position.y < 0.0 {*velocity = -*velocity; *desired_direction = -*desired_direction;}
+    pub fn remove(&mut self, index: usize) {
+        self.id.swap_remove(index);
+        self.max_speed.swap_remove(index);
+        self.steer_strength.swap_remove(index);
+        self.wander_strength.swap_remove(index);
+        self.vision.swap_remove(index);
+        self.angle.swap_remove(index);
+        self.position.swap_remove(index);
+        self.velocity.swap_remove(index);
+        self.desired_direction.swap_remove(index);
+        self.colony.swap_remove(index);
+        self.caste.swap_remove(index);
+        self.carried.swap_remove(index);
+        self.delivered.swap_remove(index);
+        self.trip.swap_remove(index);
+        self.energy.swap_remove(index);
+        self.age.swap_remove(index);
+        self.lifespan.swap_remove(index);
+        self.state.swap_remove(index);
+        self.exhausted.swap_remove(index);
+        self.target_food_position.swap_remove(index);
+        self.target_food_index.swap_remove(index);
+    }
 
-            *angle = (velocity.y/velocity.x).atan();
-            if velocity[0] < 0.0 {*angle -= PI}
-        });
+    pub fn is_dead(&self, index: usize) -> bool {
+        self.energy[index] <= 0.0 || self.age[index] >= self.lifespan[index]
     }
 
     pub fn set_food_target(&mut self, index: usize, food_position: na::Point2<f32>, food_index: u32) {
@@ -169,7 +211,7 @@ impl Ant {
         }
     }
 
-    pub fn food_acquired(&mut self, index: usize) -> bool {
+    pub fn food_acquired(&self, index: usize) -> bool {
         match self.target_food_position[index] {
             None => false,
             Some(food_position) => {
@@ -178,18 +220,6 @@ impl Ant {
         }
     }
 
-    pub fn set_pheromones_direction(&mut self, index: usize, home_angle: Option<f32>, food_angle: Option<f32>) {
-        match home_angle {
-            None => {self.home_pheromones_direction[index] = None; },
-            Some(j) => {self.home_pheromones_direction[index] = Some(na::Vector2::new(j.cos(), j.sin()));}
-        }
-
-        match food_angle {
-            None => {self.food_pheromones_direction[index] = None; },
-            Some(j) => {self.food_pheromones_direction[index] = Some(na::Vector2::new(j.cos(), j.sin()));}
-        }
-    }
-
     pub fn set_antiparallel (&mut self, index: usize) {
         self.velocity[index] = -self.velocity[index];
     }
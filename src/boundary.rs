@@ -0,0 +1,135 @@
+use ggez::nalgebra as na;
+use serde::{Deserialize, Serialize};
+
+/// What happens to an ant that walks over the edge of the world.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BoundaryMode {
+    /// Bounce off the edge like a mirror.
+    #[default]
+    Reflect,
+    /// Leave on one side, come back on the other. Pheromone sensing and
+    /// diffusion wrap as well, but ants and predators only see food, ants
+    /// and predators on their own side of the edge.
+    Wrap,
+    /// Stop at the edge and slide along it.
+    Wall,
+    /// Die at the edge and get replaced by a fresh ant at the nest.
+    Respawn,
+}
+
+impl BoundaryMode {
+    /// Keeps the ant inside `size`. Returns `true` when the ant escaped and
+    /// has to be respawned, which only happens in `Respawn` mode.
+    pub fn apply(self, size: (f32, f32), position: &mut na::Point2<f32>,
+        velocity: &mut na::Vector2<f32>, desired_direction: &mut na::Vector2<f32>) -> bool {
+        let limits = [size.0, size.1];
+
+        for axis in 0..2 {
+            let limit = limits[axis];
+            if position[axis] >= 0.0 && position[axis] <= limit {
+                continue;
+            }
+
+            match self {
+                BoundaryMode::Reflect => {
+                    position[axis] = if position[axis] < 0.0 { -position[axis] } else { 2.0 * limit - position[axis] };
+                    position[axis] = position[axis].max(0.0).min(limit);
+                    velocity[axis] = -velocity[axis];
+                    desired_direction[axis] = -desired_direction[axis];
+                },
+                BoundaryMode::Wrap => {
+                    position[axis] = position[axis].rem_euclid(limit);
+                },
+                BoundaryMode::Wall => {
+                    position[axis] = position[axis].max(0.0).min(limit);
+                    velocity[axis] = 0.0;
+                    desired_direction[axis] = -desired_direction[axis];
+                },
+                BoundaryMode::Respawn => return true,
+            }
+        }
+
+        false
+    }
+
+    /// Maps a grid coordinate that may lie outside of `0..limit` back onto
+    /// the grid, or `None` when it falls off a non-wrapping world.
+    pub fn cell(self, coordinate: f32, limit: usize) -> Option<usize> {
+        if self == BoundaryMode::Wrap {
+            Some((coordinate.floor() as i64).rem_euclid(limit as i64) as usize)
+        } else if coordinate < 0.0 || coordinate >= limit as f32 {
+            None
+        } else {
+            Some(coordinate as usize)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: (f32, f32) = (100.0, 50.0);
+
+    // An ant that stepped 10 past the right edge, heading right.
+    fn apply(mode: BoundaryMode) -> (na::Point2<f32>, na::Vector2<f32>, na::Vector2<f32>, bool) {
+        let mut position = na::Point2::new(110.0, 20.0);
+        let mut velocity = na::Vector2::new(5.0, 1.0);
+        let mut desired_direction = na::Vector2::new(1.0, 0.0);
+        let escaped = mode.apply(SIZE, &mut position, &mut velocity, &mut desired_direction);
+        (position, velocity, desired_direction, escaped)
+    }
+
+    #[test]
+    fn reflect_mirrors_at_the_edge() {
+        let (position, velocity, desired_direction, escaped) = apply(BoundaryMode::Reflect);
+        assert_eq!(position, na::Point2::new(90.0, 20.0));
+        assert_eq!(velocity, na::Vector2::new(-5.0, 1.0));
+        assert_eq!(desired_direction, na::Vector2::new(-1.0, 0.0));
+        assert!(!escaped);
+    }
+
+    #[test]
+    fn wrap_comes_back_on_the_other_side() {
+        let (position, velocity, _, escaped) = apply(BoundaryMode::Wrap);
+        assert_eq!(position, na::Point2::new(10.0, 20.0));
+        assert_eq!(velocity, na::Vector2::new(5.0, 1.0));
+        assert!(!escaped);
+    }
+
+    #[test]
+    fn wall_stops_at_the_edge() {
+        let (position, velocity, desired_direction, escaped) = apply(BoundaryMode::Wall);
+        assert_eq!(position, na::Point2::new(100.0, 20.0));
+        assert_eq!(velocity, na::Vector2::new(0.0, 1.0));
+        assert_eq!(desired_direction, na::Vector2::new(-1.0, 0.0));
+        assert!(!escaped);
+    }
+
+    #[test]
+    fn respawn_reports_the_escape() {
+        assert!(apply(BoundaryMode::Respawn).3);
+    }
+
+    #[test]
+    fn inside_the_world_nothing_changes() {
+        for mode in [BoundaryMode::Reflect, BoundaryMode::Wrap, BoundaryMode::Wall, BoundaryMode::Respawn].iter() {
+            let mut position = na::Point2::new(0.0, 50.0);
+            let mut velocity = na::Vector2::new(-1.0, 1.0);
+            let mut desired_direction = na::Vector2::new(-1.0, 0.0);
+            assert!(!mode.apply(SIZE, &mut position, &mut velocity, &mut desired_direction));
+            assert_eq!(position, na::Point2::new(0.0, 50.0));
+            assert_eq!(velocity, na::Vector2::new(-1.0, 1.0));
+        }
+    }
+
+    #[test]
+    fn cells_wrap_or_fall_off() {
+        assert_eq!(BoundaryMode::Wrap.cell(-0.5, 10), Some(9));
+        assert_eq!(BoundaryMode::Wrap.cell(10.0, 10), Some(0));
+        assert_eq!(BoundaryMode::Reflect.cell(-0.5, 10), None);
+        assert_eq!(BoundaryMode::Wall.cell(10.0, 10), None);
+        assert_eq!(BoundaryMode::Respawn.cell(9.9, 10), Some(9));
+    }
+}
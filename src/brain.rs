@@ -0,0 +1,368 @@
+use ggez::nalgebra as na;
+use ggez::GameResult;
+use serde::{Deserialize, Serialize};
+
+use crate::caste::{Caste, CasteConfig};
+use crate::config::Config;
+use crate::neural::{Network, NeuralBrain};
+use crate::pheromone::{LayerIds, PheromoneField};
+
+/// Direction and score of the strongest reading on one pheromone layer.
+pub type PheromoneReading = Option<(na::Vector2<f32>, f32)>;
+
+/// Behavioural states. Searching ants wander and follow trails, targeting
+/// ants walk to a claimed pellet and returning ants carry food home.
+pub const SEARCH: u32 = 0;
+pub const TARGET: u32 = 1;
+pub const RETURN: u32 = 2;
+
+/// Everything an ant knows when making up its mind.
+pub struct Perception<'a> {
+    /// `Ant::id` of the ant.
+    pub id: u64,
+    pub position: na::Point2<f32>,
+    /// The direction the ant wanted to go last tick.
+    pub heading: na::Vector2<f32>,
+    pub state: u32,
+    pub caste: &'a CasteConfig,
+    pub wander_strength: f32,
+    pub carried: u32,
+    pub hungry: bool,
+    /// The trail the ant is on led to a patch that ran out.
+    pub exhausted: bool,
+    pub home: na::Point2<f32>,
+    pub at_home: bool,
+    pub target_food: Option<na::Point2<f32>>,
+    /// The claimed pellet is within reach.
+    pub food_reached: bool,
+    /// Unclaimed pellets around the ant, only counted once food is reached.
+    pub food_remaining: usize,
+    pub threat: Option<na::Point2<f32>>,
+    pub layers: LayerIds,
+    /// One reading per pheromone layer, see `layers` for which is which.
+    pub pheromones: &'a [PheromoneReading],
+    /// Where the ant is facing and the colony's trails, for brains that
+    /// smell them their own way.
+    pub angle: f32,
+    pub field: &'a PheromoneField,
+    /// A random vector in the unit square, for wandering.
+    pub random: na::Vector2<f32>,
+}
+
+/// Pheromone laid at the ant's position.
+#[derive(Copy, Clone, Debug)]
+pub enum Mark {
+    Cell(usize),
+    Disk(usize, f32),
+}
+
+pub struct Decision {
+    pub direction: na::Vector2<f32>,
+    /// State for the next tick. Searching ants may still be handed a pellet
+    /// to target by the world.
+    pub state: u32,
+    /// Pick up the claimed pellet if it is within reach.
+    pub pick_up: bool,
+    /// Unload what is carried. At the nest the food is stored, elsewhere it is lost.
+    pub drop: bool,
+    pub marks: Vec<Mark>,
+}
+
+/// The decision logic of an ant. Every colony has one, shared by all its
+/// ants and called for each of them in parallel once per tick.
+pub trait AntBrain: Send + Sync {
+    fn decide(&self, perception: &Perception) -> Decision;
+
+    /// Picks up parameters changed while running, see `World::set_param`.
+    /// Brains that copy values out of the config refresh them here.
+    fn configure(&mut self, _config: &Config) {}
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BrainConfig {
+    /// Pheromone trails, castes and predator reactions as described in the README.
+    #[default]
+    Default,
+    /// Ignores pheromones entirely: wanders until it finds food and until it
+    /// stumbles back home. Useful as a baseline.
+    Random,
+    /// A feed-forward network loaded from a file written by `--evolve`,
+    /// see `src/neural.rs`.
+    Neural { network: String },
+}
+
+pub fn from_config(brain: &BrainConfig, config: &Config) -> GameResult<Box<dyn AntBrain>> {
+    Ok(match brain {
+        BrainConfig::Default => Box::new(DefaultBrain::new(config)),
+        BrainConfig::Random => Box::new(RandomBrain),
+        BrainConfig::Neural { network } => {
+            let network = Network::load(network)?;
+            Box::new(NeuralBrain::new(network, config.pheromones.len())?)
+        },
+    })
+}
+
+pub struct DefaultBrain {
+    pub recruitment_threshold: usize,
+    pub recruitment_radius: f32,
+    pub mark_radius: f32,
+}
+
+impl DefaultBrain {
+    pub fn new(config: &Config) -> Self {
+        DefaultBrain {
+            recruitment_threshold: config.recruitment_threshold,
+            recruitment_radius: config.recruitment_radius,
+            mark_radius: config.predators.mark_radius,
+        }
+    }
+
+    fn direction(&self, p: &Perception, state: u32) -> na::Vector2<f32> {
+        let sensed = |layer: Option<usize>| layer.and_then(|j| p.pheromones[j]);
+        let wander = |direction: na::Vector2<f32>| (direction + p.random * p.wander_strength).normalize();
+
+        // Hungry searchers give up and head back to the nest to eat, as
+        // do soldiers and nurses that strayed beyond their patrol.
+        let strayed = p.caste.patrol_radius > 0.0 && na::distance(&p.home, &p.position) > p.caste.patrol_radius;
+
+        // A predator in sight overrides everything else: soldiers attack
+        // it, every other ant runs away.
+        if let Some(threat) = p.threat {
+            let away = (p.position - threat).try_normalize(f32::EPSILON).unwrap_or(p.heading);
+            if p.caste.caste == Caste::Soldier { -away } else { away }
+        } else if state == SEARCH && !p.hungry && !strayed {
+            let food = sensed(p.layers.food);
+            let repellent = match (food, sensed(p.layers.repellent)) {
+                (Some((_, food_score)), Some((j, repellent_score))) if repellent_score >= food_score => Some(j),
+                (None, Some((j, _))) => Some(j),
+                _ => None,
+            };
+            let danger = sensed(p.layers.danger).map(|(j, _)| j);
+
+            match p.caste.caste {
+                Caste::Soldier => match sensed(p.layers.alarm) {
+                    Some((j, _)) => j,
+                    None => wander(p.heading),
+                },
+                Caste::Nurse => wander(p.heading),
+                Caste::Scout => {
+                    if let Some((j, _)) = sensed(p.layers.alarm) {
+                        -j
+                    } else if let Some(j) = danger.or(repellent) {
+                        wander(-j)
+                    } else {
+                        wander(p.heading)
+                    }
+                },
+                Caste::Forager => {
+                    if let Some((j, _)) = sensed(p.layers.alarm) {
+                        -j
+                    } else if let Some(j) = danger {
+                        wander(-j)
+                    } else if let Some((j, _)) = sensed(p.layers.recruitment) {
+                        j
+                    } else if let Some(j) = repellent {
+                        wander(-j)
+                    } else if let Some((j, _)) = food {
+                        j
+                    } else {
+                        wander(p.heading)
+                    }
+                },
+            }
+        } else if state == TARGET {
+            match p.target_food {
+                Some(j) => na::Vector2::from(j - p.position).normalize(),
+                None => wander(p.heading),
+            }
+        } else {
+            let to_home = p.home - p.position;
+            let dist_sq = na::distance_squared(&p.home, &p.position);
+            if dist_sq < f32::powi(100.0, 2) {
+                to_home
+            } else {
+                match sensed(p.layers.home) {
+                    None => wander(p.heading),
+                    Some((j, _)) => j,
+                }
+            }
+        }
+    }
+}
+
+impl AntBrain for DefaultBrain {
+    fn decide(&self, p: &Perception) -> Decision {
+        let mut marks = Vec::new();
+
+        // An emptied patch marks the trail as exhausted, a rich one recruits
+        // nearby searchers. Ants keep collecting until they are full or the
+        // patch runs out.
+        let pick_up = p.food_reached;
+        let mut exhausted = p.exhausted;
+        let mut full = false;
+        if pick_up {
+            full = p.carried + 1 >= p.caste.carrying_capacity || p.food_remaining == 0;
+            exhausted = p.food_remaining == 0;
+            if p.food_remaining >= self.recruitment_threshold {
+                if let Some(layer) = p.layers.recruitment {
+                    marks.push(Mark::Disk(layer, self.recruitment_radius));
+                }
+            }
+        }
+
+        let mut state = match (pick_up, full) {
+            (true, true) => RETURN,
+            (true, false) => SEARCH,
+            (false, _) => p.state,
+        };
+        if p.at_home {
+            state = SEARCH;
+            exhausted = false;
+        }
+
+        let layer = if state != RETURN {
+            p.layers.home
+        } else if exhausted {
+            p.layers.repellent
+        } else {
+            p.layers.food
+        };
+        if let Some(layer) = layer {
+            marks.push(Mark::Cell(layer));
+        }
+
+        // Ants that spot a predator sound the alarm and mark the area.
+        if p.threat.is_some() {
+            if let Some(layer) = p.layers.alarm {
+                marks.push(Mark::Cell(layer));
+            }
+            if let Some(layer) = p.layers.danger {
+                marks.push(Mark::Disk(layer, self.mark_radius));
+            }
+        }
+
+        Decision {
+            direction: self.direction(p, state),
+            state,
+            pick_up,
+            drop: p.at_home,
+            marks,
+        }
+    }
+
+    fn configure(&mut self, config: &Config) {
+        *self = DefaultBrain::new(config);
+    }
+}
+
+pub struct RandomBrain;
+
+impl AntBrain for RandomBrain {
+    fn decide(&self, p: &Perception) -> Decision {
+        let wander = (p.heading + p.random * p.wander_strength).normalize();
+        let full = p.food_reached && p.carried + 1 >= p.caste.carrying_capacity;
+        let state = if p.at_home {
+            SEARCH
+        } else if full {
+            RETURN
+        } else if p.food_reached {
+            SEARCH
+        } else {
+            p.state
+        };
+
+        let direction = match (state, p.target_food) {
+            (TARGET, Some(food)) => na::Vector2::from(food - p.position).normalize(),
+            _ => wander,
+        };
+
+        Decision { direction, state, pick_up: p.food_reached, drop: p.at_home, marks: Vec::new() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::boundary::BoundaryMode;
+
+    // A forager carrying `carried` pellets in `state`, with nothing sensed.
+    fn decide(state: u32, carried: u32, edit: impl FnOnce(&mut Perception)) -> Decision {
+        let config = Config::default();
+        let caste = CasteConfig::defaults().into_iter().find(|caste| caste.caste == Caste::Forager).unwrap();
+        let field = PheromoneField::new(&config.pheromones, 10, 10, BoundaryMode::Reflect);
+        let pheromones = vec![None; config.pheromones.len()];
+        let mut perception = Perception {
+            id: 0,
+            position: na::Point2::new(300.0, 300.0),
+            heading: na::Vector2::new(1.0, 0.0),
+            state,
+            caste: &caste,
+            wander_strength: 0.1,
+            carried,
+            hungry: false,
+            exhausted: false,
+            home: na::Point2::new(100.0, 100.0),
+            at_home: false,
+            target_food: None,
+            food_reached: false,
+            food_remaining: 0,
+            threat: None,
+            layers: LayerIds::new(&config.pheromones),
+            pheromones: &pheromones,
+            angle: 0.0,
+            field: &field,
+            random: na::Vector2::new(0.0, 0.0),
+        };
+        edit(&mut perception);
+        DefaultBrain::new(&config).decide(&perception)
+    }
+
+    fn marks_cell(decision: &Decision, layer: Option<usize>) -> bool {
+        decision.marks.iter().any(|mark| matches!(mark, Mark::Cell(j) if Some(*j) == layer))
+    }
+
+    #[test]
+    fn ants_at_home_unload_and_search() {
+        let decision = decide(RETURN, 3, |p| p.at_home = true);
+        assert_eq!(decision.state, SEARCH);
+        assert!(decision.drop);
+        assert!(!decision.pick_up);
+        assert!(marks_cell(&decision, LayerIds::new(&Config::default().pheromones).home));
+    }
+
+    #[test]
+    fn full_ants_return() {
+        let layers = LayerIds::new(&Config::default().pheromones);
+        let target = na::Point2::new(302.0, 300.0);
+        let decision = decide(TARGET, 2, |p| {
+            p.target_food = Some(target);
+            p.food_reached = true;
+            p.food_remaining = 1;
+        });
+        assert!(decision.pick_up);
+        assert!(!decision.drop);
+        assert_eq!(decision.state, RETURN);
+        assert!(marks_cell(&decision, layers.food));
+
+        // With room left they keep collecting.
+        let decision = decide(TARGET, 0, |p| {
+            p.target_food = Some(target);
+            p.food_reached = true;
+            p.food_remaining = 1;
+        });
+        assert!(decision.pick_up);
+        assert_eq!(decision.state, SEARCH);
+    }
+
+    #[test]
+    fn emptied_patches_are_marked_as_exhausted() {
+        let layers = LayerIds::new(&Config::default().pheromones);
+        let decision = decide(TARGET, 0, |p| {
+            p.target_food = Some(na::Point2::new(302.0, 300.0));
+            p.food_reached = true;
+        });
+        assert_eq!(decision.state, RETURN);
+        assert!(marks_cell(&decision, layers.repellent));
+    }
+}
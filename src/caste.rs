@@ -0,0 +1,97 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::config::EnergyConfig;
+use crate::distribution::Distribution;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Caste {
+    /// Explores: wanders widely and ignores food and recruitment trails.
+    Scout,
+    /// Follows trails to food and brings it home.
+    Forager,
+    /// Stays around the nest and runs towards alarm pheromone.
+    Soldier,
+    /// Stays at the nest and speeds up the hatching of new ants.
+    Nurse,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CasteConfig {
+    pub caste: Caste,
+    /// Relative share of newly spawned ants.
+    pub ratio: f32,
+    pub max_speed: Distribution,
+    pub steer_strength: Distribution,
+    pub wander_strength: Distribution,
+    /// Radius in which food is spotted.
+    pub vision: Distribution,
+    /// Pellets carried before heading home, zero for castes that don't forage.
+    pub carrying_capacity: u32,
+    /// Soldiers and nurses return to the nest when further than this.
+    #[serde(default)]
+    pub patrol_radius: f32,
+}
+
+/// The behavioural parameters of one ant, drawn from its caste's
+/// distributions at spawn.
+#[derive(Copy, Clone, Debug)]
+pub struct Traits {
+    pub max_speed: f32,
+    pub steer_strength: f32,
+    pub wander_strength: f32,
+    pub vision: f32,
+    pub lifespan: f32,
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct CasteStats {
+    pub births: u64,
+    pub deaths: u64,
+    /// Deaths caused by predators, included in `deaths`.
+    pub killed: u64,
+    pub food_delivered: u64,
+}
+
+impl CasteConfig {
+    fn new(caste: Caste, ratio: f32, max_speed: f32, wander_strength: f32, vision: f32,
+        carrying_capacity: u32, patrol_radius: f32) -> Self {
+        CasteConfig {
+            caste,
+            ratio,
+            max_speed: Distribution::Fixed(max_speed),
+            steer_strength: Distribution::Fixed(100.0),
+            wander_strength: Distribution::Fixed(wander_strength),
+            vision: Distribution::Fixed(vision),
+            carrying_capacity,
+            patrol_radius,
+        }
+    }
+
+    pub fn sample(&self, rng: &mut impl Rng, energy: &EnergyConfig) -> Traits {
+        Traits {
+            max_speed: self.max_speed.sample(rng),
+            steer_strength: self.steer_strength.sample(rng),
+            wander_strength: self.wander_strength.sample(rng),
+            vision: self.vision.sample(rng),
+            lifespan: energy.lifespan.sample(rng),
+        }
+    }
+
+    pub fn forages(&self) -> bool {
+        self.carrying_capacity > 0
+    }
+
+    pub fn defaults() -> Vec<CasteConfig> {
+        vec![
+            CasteConfig::new(Caste::Scout, 0.1, 70.0, 0.3, 200.0, 1, 0.0),
+            CasteConfig::new(Caste::Forager, 0.7, 50.0, 0.1, 150.0, 3, 0.0),
+            CasteConfig {
+                steer_strength: Distribution::Fixed(120.0),
+                ..CasteConfig::new(Caste::Soldier, 0.1, 45.0, 0.1, 100.0, 0, 200.0)
+            },
+            CasteConfig::new(Caste::Nurse, 0.1, 30.0, 0.2, 50.0, 0, 80.0),
+        ]
+    }
+}
@@ -0,0 +1,100 @@
+use ggez::graphics::{self, Color, DrawMode, DrawParam, MeshBuilder, Rect};
+use ggez::nalgebra as na;
+use ggez::{Context, GameResult};
+use std::collections::VecDeque;
+
+use ant_simulation::metrics::{Sample, Sampler};
+use ant_simulation::world::World;
+use ant_simulation::{HEIGHT, WIDTH};
+
+const SAMPLE_EVERY: u64 = 10;
+const HISTORY: usize = 300;
+const CHART_WIDTH: f32 = 300.0;
+const CHART_HEIGHT: f32 = 70.0;
+// Room above every chart for its title.
+const TITLE_HEIGHT: f32 = 20.0;
+const MARGIN: f32 = 10.0;
+
+// The colour and values of one line.
+type Line = (Color, Vec<f32>);
+
+/// Small line graphs of recent colony statistics in the bottom right corner,
+/// toggled with `C`. Nothing is sampled while the panel is hidden, and the
+/// graphs start over when it is shown again.
+pub struct Charts {
+    pub visible: bool,
+    sampler: Sampler,
+    history: VecDeque<Sample>,
+}
+
+impl Charts {
+    pub fn new() -> Self {
+        Charts {
+            visible: false,
+            sampler: Sampler::default(),
+            history: VecDeque::with_capacity(HISTORY),
+        }
+    }
+
+    pub fn record(&mut self, world: &World) {
+        if !self.visible {
+            self.history.clear();
+            return;
+        }
+        if !world.tick.is_multiple_of(SAMPLE_EVERY) {
+            return;
+        }
+        if self.history.len() == HISTORY {
+            self.history.pop_front();
+        }
+        self.history.push_back(self.sampler.sample(world));
+    }
+
+    pub fn draw(&self, ctx: &mut Context, world: &World) -> GameResult {
+        if !self.visible || self.history.len() < 2 {
+            return Ok(());
+        }
+
+        let series = |value: &dyn Fn(&Sample) -> f32| -> Vec<f32> { self.history.iter().map(value).collect() };
+        let white = Color::new(1.0, 1.0, 1.0, 1.0);
+        let layers = world.config.pheromones.iter().enumerate()
+            .map(|(l, layer)| {
+                let [r, g, b] = layer.color;
+                (Color::new(r, g, b, 1.0), series(&|sample| sample.pheromones[l] as f32))
+            })
+            .collect();
+        let charts: Vec<(&str, Vec<Line>)> = vec![
+            ("food delivered", vec![(white, series(&|sample| sample.delivered as f32))]),
+            ("searching / targeting / returning", vec![
+                (Color::new(0.0, 1.0, 1.0, 1.0), series(&|sample| sample.searching as f32)),
+                (Color::new(1.0, 1.0, 0.0, 1.0), series(&|sample| sample.targeting as f32)),
+                (Color::new(1.0, 0.0, 1.0, 1.0), series(&|sample| sample.returning as f32)),
+            ]),
+            ("pheromone totals", layers),
+        ];
+
+        let left = WIDTH - CHART_WIDTH - MARGIN;
+        let mut mesh = MeshBuilder::new();
+        for (k, (title, lines)) in charts.iter().enumerate() {
+            let top = HEIGHT - (charts.len() - k) as f32 * (CHART_HEIGHT + TITLE_HEIGHT + MARGIN) + TITLE_HEIGHT;
+            mesh.rectangle(DrawMode::fill(), Rect::new(left, top, CHART_WIDTH, CHART_HEIGHT),
+                Color::new(0.0, 0.0, 0.0, 0.6));
+
+            // Every line of a chart shares its scale.
+            let max = lines.iter().flat_map(|(_, values)| values.iter()).fold(f32::EPSILON, |a, b| a.max(*b));
+            for (color, values) in lines.iter() {
+                let points: Vec<na::Point2<f32>> = values.iter().enumerate()
+                    .map(|(i, value)| na::Point2::new(
+                        left + i as f32 / (HISTORY - 1) as f32 * CHART_WIDTH,
+                        top + CHART_HEIGHT * (1.0 - value / max)))
+                    .collect();
+                mesh.line(&points, 1.0, *color)?;
+            }
+
+            let title = graphics::Text::new(format!("{} (max {:.0})", title, max));
+            graphics::draw(ctx, &title, DrawParam::new().dest(na::Point2::new(left, top - TITLE_HEIGHT + 4.0)))?;
+        }
+        let mesh = mesh.build(ctx)?;
+        graphics::draw(ctx, &mesh, DrawParam::new())
+    }
+}
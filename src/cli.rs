@@ -0,0 +1,216 @@
+use ggez::GameResult;
+
+use ant_simulation::replay::Replay;
+use ant_simulation::session::Session;
+use ant_simulation::{config, evolution, sweep, tuning, world};
+
+pub const USAGE: &str = "usage: ant-simulation [CONFIG] [--seed SEED] [--headless TICKS] [--evolve NETWORK] [--tune CONFIG] [--sweep CSV] [--metrics FILE] [--trajectories FILE] [--frames PATH]\n       [--heatmaps DIR] [--profile PATH] [--record REPLAY | --replay REPLAY]";
+
+/// Command line options. Without `--headless`, `--evolve`, `--tune` or
+/// `--sweep` the simulation opens a window.
+#[derive(Default)]
+pub struct Args {
+    pub config: Option<String>,
+    pub seed: Option<u64>,
+    pub headless: Option<u64>,
+    /// Where to write the best network found by neuroevolution.
+    pub evolve: Option<String>,
+    /// Where to write the best config found by parameter tuning.
+    pub tune: Option<String>,
+    /// Where to write the results of a parameter sweep.
+    pub sweep: Option<String>,
+    /// Overrides `metrics.path` in the config.
+    pub metrics: Option<String>,
+    /// Overrides `trajectories.path` in the config.
+    pub trajectories: Option<String>,
+    /// Overrides `render.path` in the config.
+    pub frames: Option<String>,
+    /// Overrides `heatmaps.path` in the config.
+    pub heatmaps: Option<String>,
+    /// Overrides `profile.path` in the config.
+    pub profile: Option<String>,
+    /// Where to save a replay of the session.
+    pub record: Option<String>,
+    /// A replay to play back instead of starting from the config.
+    pub replay: Option<String>,
+}
+
+impl Args {
+    pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut parsed = Args::default();
+        while let Some(arg) = args.next() {
+            let mut number = |flag: &str| -> Result<u64, String> {
+                let value = args.next().ok_or_else(|| format!("{} needs a value", flag))?;
+                value.parse().map_err(|_| format!("{} expects a number, got {}", flag, value))
+            };
+            match arg.as_str() {
+                "--seed" => {
+                    // Replays store the seed as a TOML integer, which is signed.
+                    let seed = number("--seed")?;
+                    if seed > i64::MAX as u64 {
+                        return Err(format!("--seed must be at most {}", i64::MAX));
+                    }
+                    parsed.seed = Some(seed);
+                },
+                "--headless" => parsed.headless = Some(number("--headless")?),
+                "--evolve" => parsed.evolve = Some(args.next().ok_or("--evolve needs a file")?),
+                "--tune" => parsed.tune = Some(args.next().ok_or("--tune needs a file")?),
+                "--sweep" => parsed.sweep = Some(args.next().ok_or("--sweep needs a file")?),
+                "--metrics" => parsed.metrics = Some(args.next().ok_or("--metrics needs a file")?),
+                "--trajectories" => {
+                    parsed.trajectories = Some(args.next().ok_or("--trajectories needs a file")?)
+                },
+                "--frames" => parsed.frames = Some(args.next().ok_or("--frames needs a path")?),
+                "--heatmaps" => parsed.heatmaps = Some(args.next().ok_or("--heatmaps needs a directory")?),
+                "--profile" => parsed.profile = Some(args.next().ok_or("--profile needs a path")?),
+                "--record" => parsed.record = Some(args.next().ok_or("--record needs a file")?),
+                "--replay" => parsed.replay = Some(args.next().ok_or("--replay needs a file")?),
+                _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+                _ if parsed.config.is_none() => parsed.config = Some(arg),
+                _ => return Err(format!("unexpected argument {}", arg)),
+            }
+        }
+        // A replay brings its own seed and is not recorded again.
+        if parsed.replay.is_some() {
+            if parsed.record.is_some() {
+                return Err("--record cannot be used with --replay".to_string());
+            }
+            if parsed.seed.is_some() {
+                return Err("--seed cannot be used with --replay, the replay has its own".to_string());
+            }
+        }
+        Ok(parsed)
+    }
+
+    pub fn load_config(&self) -> GameResult<config::Config> {
+        let mut config = match &self.config {
+            Some(path) => config::Config::load(path)?,
+            None => config::Config::default(),
+        };
+        if self.seed.is_some() {
+            config.seed = self.seed;
+        }
+        // Replays need a fixed time step.
+        if self.record.is_some() && config.dt.is_none() {
+            config.dt = Some(1.0 / 60.0);
+        }
+        self.set_outputs(&mut config);
+        Ok(config)
+    }
+
+    fn set_outputs(&self, config: &mut config::Config) {
+        if self.metrics.is_some() {
+            config.metrics.path = self.metrics.clone();
+        }
+        if self.trajectories.is_some() {
+            config.trajectories.path = self.trajectories.clone();
+        }
+        if self.frames.is_some() {
+            config.render.path = self.frames.clone();
+        }
+        if self.heatmaps.is_some() {
+            config.heatmaps.path = self.heatmaps.clone();
+        }
+        if self.profile.is_some() {
+            config.profile.path = self.profile.clone();
+        }
+    }
+
+    /// Starts from `config`, or from the replay if one was given.
+    pub fn session(&self, config: config::Config) -> GameResult<Session> {
+        match &self.replay {
+            Some(path) => {
+                let mut replay = Replay::load(path)?;
+                self.set_outputs(&mut replay.config);
+                Session::replay(replay)
+            },
+            None => Session::new(config, self.record.clone()),
+        }
+    }
+}
+
+/// Runs the session without a window for `ticks` ticks, printing how the
+/// colonies are doing every simulated ten seconds and at the end.
+pub fn run_headless(mut session: Session, ticks: u64) -> GameResult {
+    let dt = session.world.config.dt.unwrap_or(1.0 / 60.0);
+    println!("seed {}", session.world.seed);
+    let report_every = ((10.0 / dt) as u64).max(1);
+    for _ in 0..ticks {
+        session.step(dt)?;
+        let world = &session.world;
+        if world.tick.is_multiple_of(report_every) || world.tick == ticks {
+            report(world);
+        }
+    }
+    session.finish()
+}
+
+fn report(world: &world::World) {
+    let food_left = world.food.position.iter().filter(|position| position.is_some()).count();
+    println!("tick {}: {} ants, {} pellets left, {} eaten by predators", world.tick, world.ants.len(), food_left,
+        world.predators.kills);
+    for (c, colony) in world.colonies.iter().enumerate() {
+        let population = world.ants.colony.iter().filter(|&&ant| ant == c).count();
+        println!("  colony {}: {} ants, {} delivered, {:.1} stored{}", c, population, colony.food_delivered,
+            colony.food_store, if colony.active { "" } else { " (abandoned)" });
+    }
+}
+
+/// Evolves a network as set up in `[evolution]` and saves the best one of
+/// every generation to `path`.
+pub fn run_evolution(config: config::Config, path: &str) -> GameResult {
+    let mut saved = Ok(());
+    evolution::evolve(&config, |generation, best, best_fitness, mean_fitness| {
+        println!("generation {}: best {:.4} food/tick, mean {:.4}", generation, best_fitness, mean_fitness);
+        if saved.is_ok() {
+            saved = best.save(path);
+        }
+    })?;
+    saved?;
+    println!("saved the best network to {}", path);
+    Ok(())
+}
+
+/// Tunes the `[tuning]` parameters and saves the best config so far after
+/// every generation to `path`.
+pub fn run_tuning(config: config::Config, path: &str) -> GameResult {
+    let mut saved = Ok(());
+    tuning::tune(&config, |generation, best, best_fitness, mean_fitness| {
+        println!("generation {}: best {:.4} food/tick, mean {:.4}", generation, best_fitness, mean_fitness);
+        for param in config.tuning.params.iter() {
+            println!("  {} = {}", param.path, best.get_param(&param.path).unwrap_or(f64::NAN));
+        }
+        if saved.is_ok() {
+            saved = best.save(path);
+        }
+    })?;
+    saved?;
+    println!("saved the best config to {}", path);
+    Ok(())
+}
+
+/// Runs the `[sweep]` and writes one CSV line per run to `path`.
+pub fn run_sweep(config: config::Config, path: &str) -> GameResult {
+    let start = std::time::Instant::now();
+    let runs = sweep::sweep(&config)?;
+    sweep::write_csv(path, &config.sweep.params, &runs, config.sweep.ticks)?;
+    println!("wrote {} runs to {} in {:.1?}", runs.len(), path, start.elapsed());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args, String> {
+        Args::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn replays_take_no_seed_or_recording() {
+        assert!(parse(&["--replay", "a.toml", "--headless", "10"]).is_ok());
+        assert!(parse(&["--record", "a.toml", "--seed", "3"]).is_ok());
+        assert!(parse(&["--replay", "a.toml", "--record", "b.toml"]).is_err());
+        assert!(parse(&["--seed", "3", "--replay", "a.toml"]).is_err());
+    }
+}
@@ -0,0 +1,72 @@
+use ggez::nalgebra as na;
+use ggez::GameResult;
+
+use crate::brain::{self, AntBrain};
+use crate::caste::CasteStats;
+use crate::config::{ColonyConfig, Config, ReproductionConfig};
+use crate::home::Home;
+use crate::pheromone::PheromoneField;
+use crate::sensor::{self, Sensor};
+
+/// A nest together with its own pheromone trails and the way its ants sense them.
+pub struct Colony {
+    pub home: Home,
+    pub sensor: Box<dyn Sensor>,
+    pub brain: Box<dyn AntBrain>,
+    pub pheromones: PheromoneField,
+    /// Food brought back to the nest and not eaten yet.
+    pub food_store: f32,
+    pub food_delivered: u64,
+    /// Trips that brought food home, and the distance walked on them.
+    pub trips: u64,
+    pub trip_distance: f64,
+    pub reproduction: ReproductionConfig,
+    /// Births, deaths and deliveries, indexed like the configured castes.
+    pub caste_stats: Vec<CasteStats>,
+    /// Cleared when the nest is removed by the scenario.
+    pub active: bool,
+    // Fractional births carried over between ticks.
+    birth_progress: f32,
+}
+
+impl Colony {
+    pub fn new(colony: &ColonyConfig, config: &Config, width: usize, height: usize, seed: u64) -> GameResult<Self> {
+        Ok(Colony {
+            home: Home::new(na::Point2::new(colony.x, colony.y), colony.radius, seed),
+            sensor: sensor::from_config(&colony.sensor),
+            brain: brain::from_config(&colony.brain, config)?,
+            pheromones: PheromoneField::new(&config.pheromones, width, height, config.boundary),
+            food_store: colony.food_store,
+            food_delivered: 0,
+            trips: 0,
+            trip_distance: 0.0,
+            reproduction: colony.reproduction.clone(),
+            caste_stats: vec![CasteStats::default(); config.castes.len()],
+            active: true,
+            birth_progress: 0.0,
+        })
+    }
+
+    /// Number of ants to hatch this tick, paid for out of the food store.
+    /// Every nurse speeds hatching up by `nurse_bonus`.
+    pub fn births(&mut self, dt: f32, population: usize, nurses: usize) -> usize {
+        let reproduction = &self.reproduction;
+        let surplus = self.food_store - reproduction.reserve;
+        if !self.active || surplus < reproduction.cost_per_ant || population >= reproduction.max_population {
+            self.birth_progress = 0.0;
+            return 0;
+        }
+
+        let affordable = surplus / reproduction.cost_per_ant;
+        let care = 1.0 + reproduction.nurse_bonus * nurses as f32 / population.max(1) as f32;
+        let rate = (reproduction.birth_rate * affordable * care).min(reproduction.max_births_per_second);
+        self.birth_progress += rate * dt;
+
+        let births = (self.birth_progress.floor() as usize)
+            .min(affordable as usize)
+            .min(reproduction.max_population - population);
+        self.birth_progress -= births as f32;
+        self.food_store -= births as f32 * reproduction.cost_per_ant;
+        births
+    }
+}
@@ -0,0 +1,480 @@
+use ggez::{GameError, GameResult};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+use crate::boundary::BoundaryMode;
+use crate::brain::BrainConfig;
+use crate::caste::CasteConfig;
+use crate::distribution::Distribution;
+use crate::evolution::EvolutionConfig;
+use crate::heatmap::HeatmapConfig;
+use crate::metrics::MetricsConfig;
+use crate::pheromone;
+use crate::profile::ProfileConfig;
+use crate::render::RenderConfig;
+use crate::scenario::{Event, ScheduledEvent};
+use crate::sensor::SensorConfig;
+use crate::sweep::SweepConfig;
+use crate::terrain::TerrainConfig;
+use crate::trajectory::TrajectoryConfig;
+use crate::tuning::TuningConfig;
+use crate::{HEIGHT, WIDTH};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub colonies: Vec<ColonyConfig>,
+    pub boundary: BoundaryMode,
+    pub energy: EnergyConfig,
+    pub terrain: TerrainConfig,
+    pub castes: Vec<CasteConfig>,
+    pub pheromones: Vec<PheromoneConfig>,
+    pub danger_zones: Vec<DangerZone>,
+    pub predators: PredatorConfig,
+    /// Events fired at given ticks, see `src/scenario.rs`.
+    pub scenario: Vec<ScheduledEvent>,
+    /// Rhai script driving the experiment, see `src/script.rs`.
+    pub script: Option<String>,
+    /// Seed for every random choice in the world. Runs with the same seed,
+    /// config and time step are identical; unset picks a fresh seed.
+    pub seed: Option<u64>,
+    /// Fixed time step in seconds. The window uses the frame time when unset,
+    /// headless runs fall back to 1/60.
+    pub dt: Option<f32>,
+    /// Neuroevolution settings, see `src/evolution.rs`.
+    pub evolution: EvolutionConfig,
+    /// Parameter search settings, see `src/tuning.rs`.
+    pub tuning: TuningConfig,
+    /// Parameter sweep settings, see `src/sweep.rs`.
+    pub sweep: SweepConfig,
+    /// Time-series output, see `src/metrics.rs`.
+    pub metrics: MetricsConfig,
+    /// Per-ant path recording, see `src/trajectory.rs`.
+    pub trajectories: TrajectoryConfig,
+    /// Frames rendered without a display, see `src/render.rs`.
+    pub render: RenderConfig,
+    /// Pheromone grid dumps, see `src/heatmap.rs`.
+    pub heatmaps: HeatmapConfig,
+    /// Per-phase timings, see `src/profile.rs`.
+    pub profile: ProfileConfig,
+    pub deposit_strength: f32,
+    pub recruitment_threshold: usize,
+    pub recruitment_radius: f32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PheromoneConfig {
+    pub name: String,
+    pub decay: f32,
+    #[serde(default)]
+    pub diffusion: f32,
+    pub color: [f32; 3],
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ColonyConfig {
+    pub x: f32,
+    pub y: f32,
+    pub radius: f32,
+    pub ants: usize,
+    pub food_store: f32,
+    pub sensor: SensorConfig,
+    pub brain: BrainConfig,
+    pub reproduction: ReproductionConfig,
+}
+
+/// The nest turns stored food into new ants. The birth rate grows with the
+/// number of ants the store could pay for beyond `reserve`, which is kept
+/// back for feeding the existing population.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReproductionConfig {
+    pub cost_per_ant: f32,
+    pub max_population: usize,
+    pub reserve: f32,
+    /// Births per second for every ant the surplus could pay for.
+    pub birth_rate: f32,
+    pub max_births_per_second: f32,
+    /// Extra birth rate when the whole colony were nurses.
+    pub nurse_bonus: f32,
+}
+
+/// Energy is measured so that a freshly spawned ant starts with
+/// `max_energy`. Lifespans are in seconds.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EnergyConfig {
+    pub max_energy: f32,
+    pub idle_drain: f32,
+    pub movement_drain: f32,
+    pub hunger_threshold: f32,
+    pub energy_per_food: f32,
+    pub lifespan: Distribution,
+}
+
+/// Predators roam the world, chase the nearest ant within `hunt_radius` and
+/// eat it once within `kill_radius`, then rest for `digest_time` seconds.
+/// Ants that see one within `detection_radius` flee, raise the alarm and mark
+/// the area with danger pheromone; soldiers attack instead.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PredatorConfig {
+    pub count: usize,
+    pub speed: f32,
+    pub chase_speed: f32,
+    pub hunt_radius: f32,
+    pub kill_radius: f32,
+    pub digest_time: f32,
+    pub health: f32,
+    /// Health taken per second by every soldier within `kill_radius`.
+    pub soldier_damage: f32,
+    pub detection_radius: f32,
+    pub mark_radius: f32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DangerZone {
+    pub x: f32,
+    pub y: f32,
+    pub radius: f32,
+}
+
+impl PheromoneConfig {
+    fn new(name: &str, decay: f32, diffusion: f32, color: [f32; 3]) -> Self {
+        PheromoneConfig {
+            name: name.to_string(),
+            decay,
+            diffusion,
+            color,
+        }
+    }
+}
+
+impl Default for ColonyConfig {
+    fn default() -> Self {
+        ColonyConfig {
+            x: WIDTH / 2.0,
+            y: HEIGHT / 2.0,
+            radius: 50.0,
+            ants: 1000,
+            food_store: 100.0,
+            sensor: SensorConfig::default(),
+            brain: BrainConfig::default(),
+            reproduction: ReproductionConfig::default(),
+        }
+    }
+}
+
+impl Default for ReproductionConfig {
+    fn default() -> Self {
+        ReproductionConfig {
+            cost_per_ant: 5.0,
+            max_population: 3000,
+            reserve: 20.0,
+            birth_rate: 0.05,
+            max_births_per_second: 20.0,
+            nurse_bonus: 2.0,
+        }
+    }
+}
+
+impl ReproductionConfig {
+    /// Births are paid for by dividing the surplus by `cost_per_ant`, so it
+    /// needs to be positive; the rest cannot be negative. `section` names
+    /// the colony in errors.
+    pub fn validate(&self, section: &str) -> GameResult {
+        if !self.cost_per_ant.is_finite() || self.cost_per_ant <= 0.0 {
+            return Err(GameError::ConfigError(format!("{}.cost_per_ant must be above 0, got {}", section,
+                self.cost_per_ant)));
+        }
+        let rates = [("reserve", self.reserve), ("birth_rate", self.birth_rate),
+            ("max_births_per_second", self.max_births_per_second), ("nurse_bonus", self.nurse_bonus)];
+        for (name, value) in rates.iter() {
+            if !value.is_finite() || *value < 0.0 {
+                return Err(GameError::ConfigError(format!("{}.{} must be at least 0, got {}", section, name, value)));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for EnergyConfig {
+    fn default() -> Self {
+        EnergyConfig {
+            max_energy: 100.0,
+            idle_drain: 0.5,
+            movement_drain: 0.02,
+            hunger_threshold: 30.0,
+            energy_per_food: 50.0,
+            lifespan: Distribution::Uniform { min: 240.0, max: 360.0 },
+        }
+    }
+}
+
+impl EnergyConfig {
+    /// Food is turned into energy by dividing by `energy_per_food`, so it
+    /// and `max_energy` need to be positive; drains cannot feed an ant.
+    pub fn validate(&self) -> GameResult {
+        let positive = [("max_energy", self.max_energy), ("energy_per_food", self.energy_per_food)];
+        let drains = [("idle_drain", self.idle_drain), ("movement_drain", self.movement_drain)];
+        for (name, value) in positive.iter() {
+            if !value.is_finite() || *value <= 0.0 {
+                return Err(GameError::ConfigError(format!("energy.{} must be above 0, got {}", name, value)));
+            }
+        }
+        for (name, value) in drains.iter() {
+            if !value.is_finite() || *value < 0.0 {
+                return Err(GameError::ConfigError(format!("energy.{} must be at least 0, got {}", name, value)));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for PredatorConfig {
+    fn default() -> Self {
+        PredatorConfig {
+            count: 0,
+            speed: 20.0,
+            chase_speed: 60.0,
+            hunt_radius: 120.0,
+            kill_radius: 6.0,
+            digest_time: 3.0,
+            health: 100.0,
+            soldier_damage: 10.0,
+            detection_radius: 60.0,
+            mark_radius: 10.0,
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            colonies: vec![ColonyConfig::default()],
+            boundary: BoundaryMode::default(),
+            energy: EnergyConfig::default(),
+            terrain: TerrainConfig::default(),
+            castes: CasteConfig::defaults(),
+            pheromones: vec![
+                PheromoneConfig::new(pheromone::HOME, 0.009, 0.0, [0.0, 0.0, 1.0]),
+                PheromoneConfig::new(pheromone::FOOD, 0.009, 0.0, [0.0, 1.0, 0.0]),
+                PheromoneConfig::new(pheromone::ALARM, 0.02, 0.2, [1.0, 0.5, 0.0]),
+                PheromoneConfig::new(pheromone::REPELLENT, 0.004, 0.0, [1.0, 0.0, 1.0]),
+                PheromoneConfig::new(pheromone::RECRUITMENT, 0.01, 0.1, [1.0, 1.0, 0.0]),
+                PheromoneConfig::new(pheromone::DANGER, 0.002, 0.05, [0.6, 0.0, 0.0]),
+            ],
+            danger_zones: Vec::new(),
+            predators: PredatorConfig::default(),
+            scenario: Vec::new(),
+            script: None,
+            seed: None,
+            dt: None,
+            evolution: EvolutionConfig::default(),
+            tuning: TuningConfig::default(),
+            sweep: SweepConfig::default(),
+            metrics: MetricsConfig::default(),
+            trajectories: TrajectoryConfig::default(),
+            render: RenderConfig::default(),
+            heatmaps: HeatmapConfig::default(),
+            profile: ProfileConfig::default(),
+            deposit_strength: 1.0,
+            recruitment_threshold: 20,
+            recruitment_radius: 15.0,
+        }
+    }
+}
+
+impl Config {
+    /// Reads a config file. The script, terrain image and networks it names
+    /// are relative to the file's directory.
+    pub fn load(path: &str) -> GameResult<Self> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| GameError::ConfigError(format!("could not read {}: {}", path, e)))?;
+        let mut config: Config = toml::from_str(&contents)
+            .map_err(|e| GameError::ConfigError(format!("could not parse {}: {}", path, e)))?;
+        config.resolve_paths(Path::new(path).parent().unwrap_or_else(|| Path::new("")));
+        config.energy.validate()?;
+        for (i, colony) in config.colonies.iter().enumerate() {
+            colony.reproduction.validate(&format!("colonies.{}.reproduction", i))?;
+        }
+        for scheduled in config.scenario.iter() {
+            if let Event::AddNest(colony) = &scheduled.event {
+                colony.reproduction.validate(&format!("the nest added at tick {}: reproduction", scheduled.tick))?;
+            }
+        }
+        config.evolution.validate()?;
+        config.tuning.validate()?;
+        config.sweep.validate()?;
+        Ok(config)
+    }
+
+    // Makes the input files absolute, so the config still finds them when it
+    // is saved elsewhere, as `--tune` and `--record` do.
+    fn resolve_paths(&mut self, directory: &Path) {
+        let directory = if directory.as_os_str().is_empty() { Path::new(".") } else { directory };
+        let directory = fs::canonicalize(directory).unwrap_or_else(|_| directory.to_path_buf());
+        let resolve = |path: &mut String| *path = directory.join(&*path).to_string_lossy().into_owned();
+
+        if let Some(script) = &mut self.script {
+            resolve(script);
+        }
+        if let Some(image) = &mut self.terrain.image {
+            resolve(image);
+        }
+        let nests = self.scenario.iter_mut().filter_map(|scheduled| match &mut scheduled.event {
+            Event::AddNest(colony) => Some(colony),
+            _ => None,
+        });
+        for colony in self.colonies.iter_mut().chain(nests) {
+            if let BrainConfig::Neural { network } = &mut colony.brain {
+                resolve(network);
+            }
+        }
+    }
+
+    pub fn save(&self, path: &str) -> GameResult {
+        // Going through `toml::Value` puts plain values ahead of tables, as TOML requires.
+        let contents = toml::Value::try_from(self).and_then(|value| toml::to_string(&value))
+            .map_err(|e| GameError::ConfigError(format!("could not serialize config: {}", e)))?;
+        fs::write(path, contents).map_err(|e| GameError::ConfigError(format!("could not write {}: {}", path, e)))
+    }
+
+    /// Reads a numeric parameter by its dotted path, e.g. `energy.idle_drain`
+    /// or `colonies.0.reproduction.birth_rate`.
+    pub fn get_param(&self, path: &str) -> Option<f64> {
+        let mut root = toml::Value::try_from(self).ok()?;
+        match lookup(&mut root, path)? {
+            toml::Value::Integer(value) => Some(*value as f64),
+            toml::Value::Float(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Sets a numeric parameter by its dotted path, see `get_param`.
+    pub fn set_param(&mut self, path: &str, value: f64) -> GameResult {
+        let error = |message: &str| GameError::ConfigError(format!("could not set {}: {}", path, message));
+        let mut root = toml::Value::try_from(&*self).map_err(|e| error(&e.to_string()))?;
+        let node = lookup(&mut root, path).ok_or_else(|| error("no such parameter"))?;
+        *node = match node {
+            toml::Value::Integer(_) => toml::Value::Integer(value as i64),
+            toml::Value::Float(_) => toml::Value::Float(value),
+            _ => return Err(error("not a number")),
+        };
+        *self = root.try_into().map_err(|e| error(&e.to_string()))?;
+        Ok(())
+    }
+}
+
+// Array elements are addressed by their index.
+fn lookup<'a>(root: &'a mut toml::Value, path: &str) -> Option<&'a mut toml::Value> {
+    let mut node = root;
+    for key in path.split('.') {
+        node = match node {
+            toml::Value::Table(table) => table.get_mut(key)?,
+            toml::Value::Array(array) => array.get_mut(key.parse::<usize>().ok()?)?,
+            _ => return None,
+        };
+    }
+    Some(node)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sweep::{SweepMode, SweepParam};
+    use crate::tuning::TunedParam;
+
+    #[test]
+    fn files_are_relative_to_the_config() {
+        let config = Config::load(concat!(env!("CARGO_MANIFEST_DIR"), "/configs/example.toml")).unwrap();
+        let script = config.script.unwrap();
+        assert!(Path::new(&script).is_absolute());
+        assert!(Path::new(&script).ends_with("configs/example.rhai"));
+        assert!(Path::new(&script).exists());
+    }
+
+    #[test]
+    fn params_in_arrays_and_integers() {
+        let mut config = Config::default();
+        config.set_param("colonies.0.reproduction.birth_rate", 0.5).unwrap();
+        assert_eq!(config.colonies[0].reproduction.birth_rate, 0.5);
+        assert_eq!(config.get_param("colonies.0.reproduction.birth_rate"), Some(0.5));
+        config.set_param("pheromones.1.decay", 0.25).unwrap();
+        assert_eq!(config.pheromones[1].decay, 0.25);
+        config.set_param("pheromones.0.color.2", 0.75).unwrap();
+        assert_eq!(config.pheromones[0].color[2], 0.75);
+
+        config.set_param("recruitment_threshold", 7.0).unwrap();
+        assert_eq!(config.recruitment_threshold, 7);
+        assert_eq!(config.get_param("colonies.0.reproduction.max_population"), Some(3000.0));
+
+        assert_eq!(config.get_param("colonies.9.reproduction.birth_rate"), None);
+        assert_eq!(config.get_param("pheromones.0.name"), None);
+        assert!(config.set_param("colonies.x.radius", 1.0).is_err());
+        assert!(config.set_param("pheromones.0.name", 1.0).is_err());
+        assert!(config.set_param("recruitment_threshold", -1.0).is_err());
+    }
+
+    #[test]
+    fn bad_energy_settings_are_rejected() {
+        let mut energy = EnergyConfig::default();
+        assert!(energy.validate().is_ok());
+        energy.energy_per_food = 0.0;
+        assert!(energy.validate().is_err());
+        energy = EnergyConfig { max_energy: -1.0, ..EnergyConfig::default() };
+        assert!(energy.validate().is_err());
+        energy = EnergyConfig { idle_drain: -0.5, ..EnergyConfig::default() };
+        assert!(energy.validate().is_err());
+        energy = EnergyConfig { movement_drain: f32::NAN, ..EnergyConfig::default() };
+        assert!(energy.validate().is_err());
+    }
+
+    #[test]
+    fn bad_reproduction_settings_are_rejected() {
+        let mut reproduction = ReproductionConfig::default();
+        assert!(reproduction.validate("colonies.0.reproduction").is_ok());
+        reproduction.cost_per_ant = 0.0;
+        assert!(reproduction.validate("colonies.0.reproduction").is_err());
+        reproduction = ReproductionConfig { birth_rate: -0.1, ..ReproductionConfig::default() };
+        assert!(reproduction.validate("colonies.0.reproduction").is_err());
+        reproduction = ReproductionConfig { max_births_per_second: f32::INFINITY, ..ReproductionConfig::default() };
+        assert!(reproduction.validate("colonies.0.reproduction").is_err());
+    }
+
+    #[test]
+    fn bad_mutation_strengths_are_rejected() {
+        let mut config = Config::default();
+        assert!(config.evolution.validate().is_ok());
+        config.evolution.mutation_strength = -0.1;
+        assert!(config.evolution.validate().is_err());
+        config.tuning.mutation_strength = f32::NAN;
+        assert!(config.tuning.validate().is_err());
+    }
+
+    #[test]
+    fn bad_tuning_ranges_are_rejected() {
+        let mut config = Config::default();
+        let param = |min, max, log| TunedParam { path: "energy.idle_drain".to_string(), min, max, log };
+        config.tuning.params = vec![param(0.1, 1.0, true)];
+        assert!(config.tuning.validate().is_ok());
+        config.tuning.params = vec![param(1.0, 0.1, false)];
+        assert!(config.tuning.validate().is_err());
+        config.tuning.params = vec![param(0.0, 1.0, true)];
+        assert!(config.tuning.validate().is_err());
+    }
+
+    #[test]
+    fn empty_sweeps_are_rejected() {
+        let mut config = Config::default();
+        config.sweep.params.push(SweepParam { path: "recruitment_radius".to_string(), values: Vec::new(), min: 2.0,
+            max: 1.0 });
+        assert!(config.sweep.validate().is_err());
+        config.sweep.mode = SweepMode::Random;
+        assert!(config.sweep.validate().is_err());
+        config.sweep.params[0].max = 3.0;
+        assert!(config.sweep.validate().is_ok());
+    }
+}
@@ -0,0 +1,71 @@
+use rand::Rng;
+use rand_distr::{Distribution as _, Normal};
+use serde::{Deserialize, Serialize};
+
+/// A per-ant parameter, drawn once when the ant is spawned. In the config it
+/// is either a plain number or a table such as `{ mean = 50.0, std_dev = 5.0 }`.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Distribution {
+    Fixed(f32),
+    Uniform { min: f32, max: f32 },
+    Normal { mean: f32, std_dev: f32 },
+}
+
+impl Distribution {
+    /// Draws a value, never below zero since all parameters are magnitudes.
+    pub fn sample(&self, rng: &mut impl Rng) -> f32 {
+        let value = match *self {
+            Distribution::Fixed(value) => value,
+            Distribution::Uniform { min, max } if max > min => rng.gen_range(min..max),
+            Distribution::Uniform { min, .. } => min,
+            Distribution::Normal { mean, std_dev } => match Normal::new(mean, std_dev) {
+                Ok(normal) => normal.sample(rng),
+                Err(_) => mean,
+            },
+        };
+        value.max(0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn samples(distribution: Distribution) -> Vec<f32> {
+        let mut rng = StdRng::seed_from_u64(1);
+        (0..1000).map(|_| distribution.sample(&mut rng)).collect()
+    }
+
+    #[test]
+    fn fixed_is_always_the_value() {
+        assert!(samples(Distribution::Fixed(3.5)).iter().all(|value| *value == 3.5));
+    }
+
+    #[test]
+    fn uniform_stays_within_its_bounds() {
+        let values = samples(Distribution::Uniform { min: 2.0, max: 4.0 });
+        assert!(values.iter().all(|value| (2.0..4.0).contains(value)));
+        // Empty or inverted ranges fall back to `min`.
+        assert!(samples(Distribution::Uniform { min: 2.0, max: 2.0 }).iter().all(|value| *value == 2.0));
+        assert!(samples(Distribution::Uniform { min: 2.0, max: 1.0 }).iter().all(|value| *value == 2.0));
+    }
+
+    #[test]
+    fn normal_is_centred_on_its_mean() {
+        let values = samples(Distribution::Normal { mean: 50.0, std_dev: 5.0 });
+        let mean = values.iter().sum::<f32>() / values.len() as f32;
+        assert!((mean - 50.0).abs() < 1.0);
+        // A deviation rand_distr rejects gives the mean.
+        assert!(samples(Distribution::Normal { mean: 5.0, std_dev: f32::NAN }).iter().all(|value| *value == 5.0));
+    }
+
+    #[test]
+    fn samples_are_never_negative() {
+        assert!(samples(Distribution::Fixed(-1.0)).iter().all(|value| *value == 0.0));
+        assert!(samples(Distribution::Uniform { min: -4.0, max: -2.0 }).iter().all(|value| *value == 0.0));
+        assert!(samples(Distribution::Normal { mean: 0.0, std_dev: 10.0 }).iter().all(|value| *value >= 0.0));
+    }
+}
@@ -0,0 +1,261 @@
+use ggez::nalgebra as na;
+use ggez::{GameError, GameResult};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use crate::brain::{AntBrain, Decision, Mark, Perception, RandomBrain, RETURN, SEARCH, TARGET};
+use crate::config::Config;
+use crate::world::World;
+use crate::WIDTH;
+
+/// What a controlled ant does this tick.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Action {
+    /// Heading change in radians, relative to where the ant was going.
+    pub turn: f32,
+    /// Pick up the targeted pellet once it is within reach.
+    pub pick_up: bool,
+    /// Unload the carried food, into the store when at the nest.
+    pub drop: bool,
+    /// Pheromone layer to mark the ant's position with.
+    pub deposit: Option<usize>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Agents {
+    /// One ant of the colony is controlled, the others follow the colony's brain.
+    Single,
+    /// Every ant of the colony is controlled, including those hatched later.
+    Multi,
+}
+
+pub struct Observation {
+    /// `Ant::id` of the observed ant.
+    pub ant: u64,
+    pub values: Vec<f32>,
+}
+
+pub struct Step {
+    pub observations: Vec<Observation>,
+    /// One reward per ant of the previous observations, in the same order:
+    /// the pellets it delivered to the nest during the step.
+    pub rewards: Vec<f32>,
+    pub done: bool,
+}
+
+/// A gym-style environment over one colony of a world. Actions are handed
+/// to the colony's brain through a shared table keyed by `Ant::id`, so ants
+/// removed during the step do not shift them onto others; ants without an
+/// action fall back to the colony's own brain.
+pub struct Env {
+    config: Config,
+    colony: usize,
+    agents: Agents,
+    dt: f32,
+    max_ticks: u64,
+    pub world: World,
+    actions: Arc<RwLock<HashMap<u64, Action>>>,
+    // Ids of the last observed ants and what they had delivered by then.
+    observed: Vec<(u64, u32)>,
+}
+
+struct ExternalBrain {
+    actions: Arc<RwLock<HashMap<u64, Action>>>,
+    fallback: Box<dyn AntBrain>,
+}
+
+impl AntBrain for ExternalBrain {
+    fn decide(&self, p: &Perception) -> Decision {
+        let action = match self.actions.read().unwrap().get(&p.id) {
+            Some(action) => *action,
+            None => return self.fallback.decide(p),
+        };
+
+        let picked_up = p.food_reached && action.pick_up;
+        let state = if p.at_home && action.drop {
+            SEARCH
+        } else if picked_up && p.carried + 1 >= p.caste.carrying_capacity {
+            RETURN
+        } else if picked_up {
+            SEARCH
+        } else {
+            p.state
+        };
+
+        Decision {
+            direction: na::Rotation2::new(action.turn) * p.heading,
+            state,
+            pick_up: action.pick_up,
+            drop: action.drop,
+            marks: action.deposit.filter(|layer| *layer < p.pheromones.len()).map(Mark::Cell).into_iter().collect(),
+        }
+    }
+
+    fn configure(&mut self, config: &Config) {
+        self.fallback.configure(config);
+    }
+}
+
+impl Env {
+    /// `max_ticks` ends an episode; it also ends when no controlled ant is left.
+    pub fn new(config: Config, colony: usize, agents: Agents, max_ticks: u64) -> GameResult<Self> {
+        let seed = config.seed.unwrap_or(0);
+        let dt = config.dt.unwrap_or(1.0 / 60.0);
+        let actions = Arc::new(RwLock::new(HashMap::new()));
+        let mut env = Env {
+            world: Env::start(&config, seed, colony, &actions)?,
+            config,
+            colony,
+            agents,
+            dt,
+            max_ticks,
+            actions,
+            observed: Vec::new(),
+        };
+        let agents = env.initial_agents();
+        env.observe(agents);
+        Ok(env)
+    }
+
+    /// Values per observation: a heading-relative direction and strength for
+    /// every pheromone layer, then the nest, the targeted pellet and the
+    /// nearest predator, the state, the load, energy and whether at the nest.
+    pub fn observation_size(&self) -> usize {
+        self.config.pheromones.len() * 3 + 15
+    }
+
+    pub fn reset(&mut self, seed: u64) -> GameResult<Vec<Observation>> {
+        self.world = Env::start(&self.config, seed, self.colony, &self.actions)?;
+        let agents = self.initial_agents();
+        Ok(self.observe(agents))
+    }
+
+    /// Applies one action per ant of the last observations, in the same
+    /// order, and advances the world by one tick.
+    pub fn step(&mut self, actions: &[Action]) -> GameResult<Step> {
+        if actions.len() != self.observed.len() {
+            return Err(GameError::ConfigError(format!("got {} actions for {} observations", actions.len(),
+                self.observed.len())));
+        }
+        {
+            let mut table = self.actions.write().unwrap();
+            table.clear();
+            table.extend(self.observed.iter().zip(actions).map(|((id, _), action)| (*id, *action)));
+        }
+
+        self.world.step(self.dt)?;
+
+        let index = self.index();
+        let rewards = self.observed.iter()
+            .map(|(id, delivered)| match index.get(id) {
+                Some(&i) => (self.world.ants.delivered[i] - delivered) as f32,
+                None => 0.0,
+            })
+            .collect();
+
+        let agents = match self.agents {
+            Agents::Single => self.observed.iter().filter_map(|(id, _)| index.get(id).copied()).collect(),
+            Agents::Multi => self.colony_ants(),
+        };
+        let observations = self.observe(agents);
+        let done = observations.is_empty() || self.world.tick >= self.max_ticks;
+
+        Ok(Step { observations, rewards, done })
+    }
+
+    // A new world with the colony's brain handing out the actions.
+    fn start(config: &Config, seed: u64, colony: usize, actions: &Arc<RwLock<HashMap<u64, Action>>>)
+        -> GameResult<World> {
+        let mut world = World::new(Config { seed: Some(seed), ..config.clone() })?;
+        world.initilize_positions();
+
+        if colony >= world.colonies.len() {
+            return Err(GameError::ConfigError(format!("colony is {} but there are {} colonies", colony,
+                world.colonies.len())));
+        }
+        let colony = &mut world.colonies[colony];
+        let fallback = std::mem::replace(&mut colony.brain, Box::new(RandomBrain));
+        colony.brain = Box::new(ExternalBrain { actions: actions.clone(), fallback });
+        Ok(world)
+    }
+
+    fn initial_agents(&self) -> Vec<usize> {
+        match self.agents {
+            Agents::Single => self.world.ants.colony.iter().position(|&c| c == self.colony).into_iter().collect(),
+            Agents::Multi => self.colony_ants(),
+        }
+    }
+
+    fn index(&self) -> HashMap<u64, usize> {
+        self.world.ants.id.iter().enumerate().map(|(i, id)| (*id, i)).collect()
+    }
+
+    fn colony_ants(&self) -> Vec<usize> {
+        (0..self.world.ants.len()).filter(|&i| self.world.ants.colony[i] == self.colony).collect()
+    }
+
+    fn observe(&mut self, agents: Vec<usize>) -> Vec<Observation> {
+        self.observed = agents.iter().map(|&i| (self.world.ants.id[i], self.world.ants.delivered[i])).collect();
+        agents.into_iter().map(|i| Observation { ant: self.world.ants.id[i], values: self.features(i) }).collect()
+    }
+
+    fn features(&self, i: usize) -> Vec<f32> {
+        let world = &self.world;
+        let ants = &world.ants;
+        let colony = &world.colonies[ants.colony[i]];
+        let position = ants.position[i];
+        let angle = ants.angle[i];
+        let mut values = Vec::with_capacity(self.observation_size());
+
+        // Directions are given relative to the ant's heading.
+        let relative = |target: Option<na::Point2<f32>>| match target {
+            Some(target) => {
+                let offset = target - position;
+                let bearing = offset.y.atan2(offset.x) - angle;
+                [1.0, bearing.cos(), bearing.sin()]
+            },
+            None => [0.0, 0.0, 0.0],
+        };
+
+        for reading in colony.sensor.sense(position, angle, &colony.pheromones) {
+            match reading {
+                Some((direction, score)) => values.extend(&[(direction - angle).cos(), (direction - angle).sin(),
+                    score.tanh()]),
+                None => values.extend(&[0.0, 0.0, 0.0]),
+            }
+        }
+
+        let home = relative(Some(colony.home.position));
+        values.extend(&[home[1], home[2], na::distance(&colony.home.position, &position) / WIDTH]);
+        values.extend(&relative(ants.target_food_position[i]));
+        values.extend(&relative(world.predators.nearest(&position, world.config.predators.detection_radius)));
+
+        let state = ants.state[i];
+        values.extend(&[(state == SEARCH) as u8 as f32, (state == TARGET) as u8 as f32, (state == RETURN) as u8 as f32]);
+        let capacity = world.config.castes[ants.caste[i]].carrying_capacity.max(1);
+        values.push(ants.carried[i] as f32 / capacity as f32);
+        values.push(ants.energy[i] / world.config.energy.max_energy);
+        values.push(colony.home.touching_home(position) as u8 as f32);
+        values
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_colonies_are_rejected() {
+        let colonies = Config::default().colonies.len();
+        assert!(Env::new(Config::default(), colonies, Agents::Single, 10).is_err());
+    }
+
+    #[test]
+    fn one_action_per_observation() {
+        let mut env = Env::new(Config::default(), 0, Agents::Multi, 10).unwrap();
+        let observations = env.reset(1).unwrap();
+        assert!(!observations.is_empty());
+        assert!(env.step(&[]).is_err());
+        assert!(env.step(&vec![Action::default(); observations.len()]).is_ok());
+    }
+}
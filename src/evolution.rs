@@ -0,0 +1,157 @@
+use ggez::{GameError, GameResult};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rand_distr::{Distribution, Normal};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::neural::{self, Network, NeuralBrain};
+use crate::world::World;
+
+/// Settings of the neuroevolution loop, see `evolve`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EvolutionConfig {
+    /// Colony whose brain is evolved; the others keep their own.
+    pub colony: usize,
+    /// Sizes of the hidden layers.
+    pub hidden: Vec<usize>,
+    pub population: usize,
+    pub generations: usize,
+    /// Best genomes carried over unchanged to the next generation.
+    pub elite: usize,
+    /// Genomes drawn per tournament when picking a parent.
+    pub tournament: usize,
+    /// Chance of every weight to be mutated and the deviation it is mutated by.
+    pub mutation_rate: f32,
+    pub mutation_strength: f32,
+    /// Length of one evaluation run, and the seeds every genome is run with.
+    pub ticks: u64,
+    pub seeds: Vec<u64>,
+}
+
+impl Default for EvolutionConfig {
+    fn default() -> Self {
+        EvolutionConfig {
+            colony: 0,
+            hidden: vec![8],
+            population: 16,
+            generations: 10,
+            elite: 2,
+            tournament: 3,
+            mutation_rate: 0.1,
+            mutation_strength: 0.3,
+            ticks: 600,
+            seeds: vec![1],
+        }
+    }
+}
+
+impl EvolutionConfig {
+    /// Rejects settings the genetic algorithm cannot run with.
+    pub fn validate(&self) -> GameResult {
+        check_mutation("evolution", self.mutation_strength)
+    }
+}
+
+/// `mutation_strength` is the deviation of a normal distribution, which
+/// needs to be finite and not negative.
+pub fn check_mutation(section: &str, strength: f32) -> GameResult {
+    if !strength.is_finite() || strength < 0.0 {
+        return Err(GameError::ConfigError(format!("{}.mutation_strength must be a finite number of at least 0, got {}",
+            section, strength)));
+    }
+    Ok(())
+}
+
+/// Food delivered by the evolved colony per tick, averaged over the seeds.
+pub fn fitness(config: &Config, network: &Network) -> GameResult<f32> {
+    let settings = &config.evolution;
+    let dt = config.dt.unwrap_or(1.0 / 60.0);
+    let scores = settings.seeds.iter()
+        .map(|&seed| {
+            let mut world = World::new(Config { seed: Some(seed), script: None, ..config.clone() })?;
+            world.initilize_positions();
+            world.colonies[settings.colony].brain = Box::new(NeuralBrain::new(network.clone(),
+                config.pheromones.len())?);
+            for _ in 0..settings.ticks {
+                world.step(dt)?;
+            }
+            Ok(world.colonies[settings.colony].food_delivered as f32 / settings.ticks.max(1) as f32)
+        })
+        .collect::<GameResult<Vec<f32>>>()?;
+    Ok(scores.iter().sum::<f32>() / settings.seeds.len().max(1) as f32)
+}
+
+/// Evolves networks for `config.evolution.colony`, evaluating every
+/// generation in parallel headless worlds. `report` gets the generation,
+/// its best network and its best and mean fitness.
+pub fn evolve(config: &Config, mut report: impl FnMut(usize, &Network, f32, f32)) -> GameResult<Network> {
+    let settings = &config.evolution;
+    settings.validate()?;
+    if settings.colony >= config.colonies.len() {
+        return Err(GameError::ConfigError(format!("evolution.colony is {} but there are {} colonies", settings.colony,
+            config.colonies.len())));
+    }
+    let layers = config.pheromones.len();
+    let mut sizes = vec![neural::input_size(layers)];
+    sizes.extend(settings.hidden.iter());
+    sizes.push(neural::output_size(layers));
+
+    let mut rng = StdRng::seed_from_u64(config.seed.unwrap_or(0));
+    let mutation = Normal::new(0.0, settings.mutation_strength).unwrap();
+    let mut population: Vec<Network> = (0..settings.population.max(1))
+        .map(|_| Network::random(&sizes, &mut rng))
+        .collect();
+    let mut best = population[0].clone();
+
+    for generation in 0..settings.generations {
+        let scores = population.par_iter()
+            .map(|network| fitness(config, network))
+            .collect::<GameResult<Vec<f32>>>()?;
+        let top = (0..scores.len()).max_by(|a, b| scores[*a].total_cmp(&scores[*b])).unwrap();
+        best = population[top].clone();
+        report(generation, &best, scores[top], scores.iter().sum::<f32>() / scores.len() as f32);
+
+        let genomes: Vec<Vec<f32>> = population.iter().map(|network| network.weights.clone()).collect();
+        population = breed(&genomes, &scores, settings.elite, settings.tournament, &mut rng, |weight, rng| {
+            if rng.gen::<f32>() < settings.mutation_rate {
+                *weight += mutation.sample(rng);
+            }
+        })
+        .into_iter()
+        .map(|weights| Network { sizes: sizes.clone(), weights })
+        .collect();
+    }
+    Ok(best)
+}
+
+/// The next generation of a genetic algorithm: the `elite` best genomes
+/// unchanged, then children of tournament-picked parents with uniform
+/// crossover and `mutate` applied to every gene.
+pub fn breed(population: &[Vec<f32>], scores: &[f32], elite: usize, tournament: usize, rng: &mut StdRng,
+    mutate: impl Fn(&mut f32, &mut StdRng)) -> Vec<Vec<f32>> {
+    let mut ranked: Vec<usize> = (0..population.len()).collect();
+    ranked.sort_by(|a, b| scores[*b].total_cmp(&scores[*a]));
+    let pick = |rng: &mut StdRng| -> usize {
+        (0..tournament.max(1))
+            .map(|_| rng.gen_range(0..population.len()))
+            .max_by(|a, b| scores[*a].total_cmp(&scores[*b]))
+            .unwrap()
+    };
+
+    let mut next: Vec<Vec<f32>> = ranked.iter().take(elite).map(|&i| population[i].clone()).collect();
+    while next.len() < population.len() {
+        let (mother, father) = (pick(rng), pick(rng));
+        let mut child = population[mother].clone();
+        for (k, gene) in child.iter_mut().enumerate() {
+            if rng.gen::<bool>() {
+                *gene = population[father][k];
+            }
+            mutate(gene, rng);
+        }
+        next.push(child);
+    }
+    next
+}
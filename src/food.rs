@@ -1,32 +1,99 @@
 use ggez::nalgebra as na;
-use crate::TOTAL_FOOD;
-use rand::{Rng, thread_rng, prelude::ThreadRng};
+use crate::spatial::SpatialHash;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use std::ops::Range;
 
 pub struct Food {
-    pub position: [Option<na::Point2<f32>>; TOTAL_FOOD],
-    pub state: [u32; TOTAL_FOOD],
+    pub position: Vec<Option<na::Point2<f32>>>,
+    pub state: Vec<u32>,
     pub food_generated: u32,
-    rng: ThreadRng,
+    // Only unclaimed pellets are kept in the index.
+    index: SpatialHash,
+    rng: StdRng,
 }
 
 impl Food {
-    pub fn new () -> Self {
+    pub fn new (width: f32, height: f32, cell_size: f32, seed: u64) -> Self {
         Food {
-            position: [None; TOTAL_FOOD],
-            state: [0; TOTAL_FOOD],
+            position: Vec::new(),
+            state: Vec::new(),
             food_generated: 0,
-            rng: thread_rng(),
+            index: SpatialHash::new(width, height, cell_size),
+            rng: StdRng::seed_from_u64(seed),
         }
     }
 
-    pub fn add_food (&mut self, bottom_left: na::Point2<f32>, top_right: na::Point2<f32>, food: u32) {
+    /// Scatters `food` pellets over the rectangle and returns their indices.
+    pub fn add_food (&mut self, bottom_left: na::Point2<f32>, top_right: na::Point2<f32>, food: u32) -> Range<u32> {
         for i in self.food_generated..(self.food_generated+food) {
             let current_x = self.rng.gen_range(bottom_left.x..top_right.x);
             let current_y = self.rng.gen_range(bottom_left.y..top_right.y);
-            self.position[i as usize] = Some(na::Point2::new(current_x, current_y));
-            self.state[i as usize] = 0;
+            let position = na::Point2::new(current_x, current_y);
+            self.position.push(Some(position));
+            self.state.push(0);
+            self.index.insert(i, &position);
         }
 
         self.food_generated += food;
+        (self.food_generated - food)..self.food_generated
     }
-}
\ No newline at end of file
+
+    /// Takes a pellet out of the world, because it was eaten or its source
+    /// was removed.
+    pub fn remove (&mut self, index: u32) {
+        if self.state[index as usize] == 0 {
+            if let Some(position) = self.position[index as usize] {
+                self.index.remove(index, &position);
+            }
+        }
+        self.state[index as usize] = 2;
+        self.position[index as usize] = None;
+    }
+
+    pub fn move_by (&mut self, index: u32, offset: na::Vector2<f32>) {
+        if let Some(position) = self.position[index as usize] {
+            if self.state[index as usize] == 0 {
+                self.index.remove(index, &position);
+                self.index.insert(index, &(position + offset));
+            }
+            self.position[index as usize] = Some(position + offset);
+        }
+    }
+
+    /// Marks a pellet as targeted by an ant and takes it out of the index.
+    pub fn claim (&mut self, index: u32) {
+        if self.state[index as usize] == 0 {
+            if let Some(position) = self.position[index as usize] {
+                self.index.remove(index, &position);
+            }
+        }
+        self.state[index as usize] = 1;
+    }
+
+    /// Hands a claimed pellet back, e.g. when the ant that claimed it died.
+    pub fn release (&mut self, index: u32) {
+        if self.state[index as usize] == 1 {
+            if let Some(position) = self.position[index as usize] {
+                self.index.insert(index, &position);
+            }
+            self.state[index as usize] = 0;
+        }
+    }
+
+    pub fn nearest_unclaimed (&self, position: &na::Point2<f32>, radius: f32) -> Option<u32> {
+        self.index.nearest(position, radius, |j| self.position[j as usize])
+    }
+
+    pub fn count_unclaimed (&self, position: &na::Point2<f32>, radius: f32) -> usize {
+        let mut count = 0;
+        self.index.query(position, radius, |j| {
+            if let Some(k) = self.position[j as usize] {
+                if na::distance_squared(&k, position) < radius * radius {
+                    count += 1;
+                }
+            }
+        });
+        count
+    }
+}
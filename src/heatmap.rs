@@ -0,0 +1,185 @@
+use ggez::{GameError, GameResult};
+use image::png::PNGEncoder;
+use image::{ColorType, RgbImage};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+
+use crate::world::World;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HeatmapFormat {
+    /// `<name>.png`, 16-bit grayscale scaled to `max`.
+    Gray16,
+    /// `<name>_color.png`, 8-bit RGB through a black, purple, orange and
+    /// white colour map scaled to `max`.
+    Color,
+    /// `<name>.npy`, the raw concentrations as a NumPy array of little-endian
+    /// `f32` with shape `(height, width)`.
+    Npy,
+}
+
+/// When and how to dump the pheromone grids. Every layer of every colony is
+/// written at each of the `ticks` as `colony<c>_<layer>_<tick>` in the `path`
+/// directory. Nothing is written without a `path`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HeatmapConfig {
+    pub path: Option<String>,
+    pub ticks: Vec<u64>,
+    pub formats: Vec<HeatmapFormat>,
+    /// Concentration drawn at full brightness in the images. When not set,
+    /// every image is scaled to the largest value of its own grid.
+    pub max: Option<f32>,
+}
+
+impl Default for HeatmapConfig {
+    fn default() -> Self {
+        HeatmapConfig {
+            path: None,
+            ticks: Vec::new(),
+            formats: vec![HeatmapFormat::Gray16, HeatmapFormat::Color, HeatmapFormat::Npy],
+            max: None,
+        }
+    }
+}
+
+// Stops of the colour map, evenly spaced from zero to `max`.
+const COLOR_MAP: [[f32; 3]; 5] = [
+    [0.0, 0.0, 0.0],
+    [0.35, 0.05, 0.5],
+    [0.85, 0.25, 0.3],
+    [1.0, 0.7, 0.1],
+    [1.0, 1.0, 1.0],
+];
+
+fn color(t: f32) -> [u8; 3] {
+    let t = t.clamp(0.0, 1.0) * (COLOR_MAP.len() - 1) as f32;
+    let k = (t as usize).min(COLOR_MAP.len() - 2);
+    let f = t - k as f32;
+    let mut rgb = [0; 3];
+    for (c, channel) in rgb.iter_mut().enumerate() {
+        *channel = ((COLOR_MAP[k][c] * (1.0 - f) + COLOR_MAP[k + 1][c] * f) * 255.0).round() as u8;
+    }
+    rgb
+}
+
+/// Writes the pheromone grids at the chosen ticks.
+pub struct Heatmaps {
+    config: HeatmapConfig,
+    path: String,
+}
+
+impl Heatmaps {
+    pub fn create(path: &str, config: &HeatmapConfig) -> GameResult<Self> {
+        fs::create_dir_all(path).map_err(|e| error(path, e))?;
+        Ok(Heatmaps { config: config.clone(), path: path.to_string() })
+    }
+
+    pub fn record(&self, world: &World) -> GameResult {
+        if self.config.ticks.contains(&world.tick) {
+            self.write(world)?;
+        }
+        Ok(())
+    }
+
+    /// Writes every layer of every colony as it is now.
+    pub fn write(&self, world: &World) -> GameResult {
+        for (c, colony) in world.colonies.iter().enumerate() {
+            for (layer, config) in colony.pheromones.layers.iter().zip(world.config.pheromones.iter()) {
+                let name = format!("{}/colony{}_{}_{:06}", self.path, c, config.name, world.tick);
+                let values: Vec<f32> = layer.grid.iter().flatten().copied().collect();
+                let (width, height) = (layer.grid[0].len(), layer.grid.len());
+                let max = self.config.max
+                    .unwrap_or_else(|| values.iter().fold(0.0, |a: f32, b| a.max(*b)))
+                    .max(f32::EPSILON);
+
+                for format in self.config.formats.iter() {
+                    match format {
+                        HeatmapFormat::Gray16 => {
+                            let path = format!("{}.png", name);
+                            // PNG stores 16-bit samples big-endian.
+                            let bytes: Vec<u8> = values.iter()
+                                .map(|value| ((value / max).min(1.0) * u16::MAX as f32).round() as u16)
+                                .flat_map(|value| value.to_be_bytes().to_vec())
+                                .collect();
+                            let file = File::create(&path).map_err(|e| error(&path, e))?;
+                            PNGEncoder::new(BufWriter::new(file))
+                                .encode(&bytes, width as u32, height as u32, ColorType::Gray(16))
+                                .map_err(|e| error(&path, e))?;
+                        },
+                        HeatmapFormat::Color => {
+                            let path = format!("{}_color.png", name);
+                            let bytes = values.iter().flat_map(|value| color(value / max).to_vec()).collect();
+                            RgbImage::from_raw(width as u32, height as u32, bytes).unwrap()
+                                .save(&path)
+                                .map_err(|e| error(&path, e))?;
+                        },
+                        HeatmapFormat::Npy => {
+                            let path = format!("{}.npy", name);
+                            write_npy(&path, &values, width, height).map_err(|e| error(&path, e))?;
+                        },
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// Version 1.0 of the format: magic, version, header length, then a Python
+// dict literal padded with spaces so the data starts on a 64 byte boundary.
+fn write_npy(path: &str, values: &[f32], width: usize, height: usize) -> std::io::Result<()> {
+    let mut header = format!("{{'descr': '<f4', 'fortran_order': False, 'shape': ({}, {}), }}", height, width);
+    let unpadded = 10 + header.len() + 1;
+    header.push_str(&" ".repeat((64 - unpadded % 64) % 64));
+    header.push('\n');
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    writer.write_all(b"\x93NUMPY\x01\x00")?;
+    writer.write_all(&(header.len() as u16).to_le_bytes())?;
+    writer.write_all(header.as_bytes())?;
+    for value in values {
+        writer.write_all(&value.to_le_bytes())?;
+    }
+    writer.flush()
+}
+
+fn error(path: &str, e: impl std::fmt::Display) -> GameError {
+    GameError::ConfigError(format!("could not write {}: {}", path, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn npy_header_is_padded() {
+        let path = std::env::temp_dir().join(format!("ant-simulation-heatmap-{}.npy", std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+        let values = [0.0, 1.5, -2.0, 3.25, 4.0, 5.0];
+        write_npy(&path, &values, 3, 2).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(&bytes[..8], b"\x93NUMPY\x01\x00");
+        let length = u16::from_le_bytes([bytes[8], bytes[9]]) as usize;
+        assert_eq!((10 + length) % 64, 0);
+        let header = std::str::from_utf8(&bytes[10..10 + length]).unwrap();
+        assert!(header.starts_with("{'descr': '<f4', 'fortran_order': False, 'shape': (2, 3), }"));
+        assert!(header.ends_with('\n'));
+
+        let data: Vec<f32> = bytes[10 + length..].chunks(4)
+            .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+            .collect();
+        assert_eq!(data, values);
+    }
+
+    #[test]
+    fn color_map_ends() {
+        assert_eq!(color(0.0), [0, 0, 0]);
+        assert_eq!(color(1.0), [255, 255, 255]);
+        assert_eq!(color(2.0), [255, 255, 255]);
+    }
+}
@@ -1,20 +1,20 @@
 use ggez::nalgebra as na;
-use rand::thread_rng;
 use std::f32::consts::PI;
-use rand::{Rng, prelude::ThreadRng};
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
 
 pub struct Home {
     pub position: na::Point2<f32>,
     pub radius: f32,
-    rng: ThreadRng,
+    rng: StdRng,
 }
 
 impl Home {
-    pub fn new (position: na::Point2<f32>, radius: f32) -> Self {
+    pub fn new (position: na::Point2<f32>, radius: f32, seed: u64) -> Self {
         Home {
-            position: position,
-            radius: radius,
-            rng: thread_rng(),
+            position,
+            radius,
+            rng: StdRng::seed_from_u64(seed),
         }
     } 
 
@@ -28,7 +28,6 @@ impl Home {
     }
 
     pub fn touching_home (&self, position: na::Point2<f32>) -> bool {
-        let touching = (position - self.position).norm() <= self.radius;
-        return touching;
+        (position - self.position).norm() <= self.radius
     }
 }
\ No newline at end of file
@@ -0,0 +1,38 @@
+//! The simulation itself, independent of the window. `src/main.rs` draws it
+//! with ggez; `env` exposes it as a reinforcement-learning environment.
+
+pub mod ant;
+pub mod home;
+pub mod utils;
+pub mod food;
+pub mod config;
+pub mod pheromone;
+pub mod sensor;
+pub mod colony;
+pub mod spatial;
+pub mod world;
+pub mod boundary;
+pub mod caste;
+pub mod distribution;
+pub mod predator;
+pub mod terrain;
+pub mod scenario;
+pub mod script;
+pub mod brain;
+pub mod env;
+pub mod neural;
+pub mod evolution;
+pub mod tuning;
+pub mod sweep;
+pub mod metrics;
+pub mod trajectory;
+pub mod replay;
+pub mod session;
+pub mod render;
+pub mod heatmap;
+pub mod profile;
+
+const TOTAL_FOOD: usize = 10000;
+pub const WIDTH : f32 = 1500.0;
+pub const HEIGHT : f32 = 900.0;
+const ANT_VISION : f32 = 150.0;
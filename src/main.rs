@@ -1,172 +1,74 @@
 use ggez::{Context, ContextBuilder, GameResult};
-use ggez::event::{self, EventHandler};
+use ggez::event::{self, EventHandler, KeyCode, KeyMods, MouseButton};
 use ggez::nalgebra as na;
 use ggez::graphics;
 use ggez::conf;
-use std::cmp::{max, min};
-use std::{f32, usize};
-use arrayvec::ArrayVec;
-// use rayon::prelude::*;
-use std::f32::consts::PI;
-
-mod ant;
-mod home;
-mod utils;
-mod food;
-
-const TOTAL_ANTS: usize = 1000;
-const TOTAL_FOOD: usize = 10000;
-const ANT_RADIUS: u16 = 1;
+use std::env;
+use std::process;
+
+mod charts;
+mod cli;
+
+use ant_simulation::replay::Intervention;
+use ant_simulation::scenario::Event;
+use ant_simulation::session::Session;
+use ant_simulation::{HEIGHT, WIDTH};
+
 const FOOD_RADIUS: u16 = 1;
-const WIDTH : f32 = 1500.0;
-const HEIGHT : f32 = 900.0;
-const HOME_X : f32 = WIDTH/2.0;
-const HOME_Y : f32 = HEIGHT/2.0;
-const HOME_RADIUS : f32 = 50.0;
-const ANT_VISION : f32 = 150.0;
-const PHEROMONE_DECAY : f32 = 0.009;
 
 struct MainState {
-    ants: ant::Ant,
-    home: home::Home,
-    food: food::Food,
-    home_pheromones: Vec<Vec<f32>>,
-    food_pheromones: Vec<Vec<f32>>,
+    session: Session,
+    charts: charts::Charts,
+    // Whether the time spent per phase is shown.
+    profile_visible: bool,
+    // Background image and the terrain version it was rendered from.
+    terrain_image: Option<(u32, graphics::Image)>,
 }
 
 impl MainState {
-    pub fn new(ctx: &mut Context) -> Self {
-        let (screen_w, screen_h) = graphics::drawable_size(ctx);
-        let home_position = na::Point2::new(HOME_X, HOME_Y);
-
+    pub fn new(session: Session) -> Self {
         MainState {
-            ants: ant::Ant::new(screen_w, screen_h),
-            home: home::Home::new(home_position, HOME_RADIUS),
-            food: food::Food::new(),
-            home_pheromones: vec![vec![0.0; WIDTH as usize]; HEIGHT as usize],
-            food_pheromones: vec![vec![0.0; WIDTH as usize]; HEIGHT as usize],
+            session,
+            charts: charts::Charts::new(),
+            profile_visible: false,
+            terrain_image: None,
         }
     }
-
-    pub fn initilize_positions(&mut self) {
-        let mut init_data = ArrayVec::<(na::Point2<f32>, f32), TOTAL_ANTS>::new();
-        for i in 0..TOTAL_ANTS {init_data.insert(i, self.home.generate_starting_position());}
-        let init_data = init_data.into_inner().unwrap();
-        self.ants.initialize(&init_data);
-        
-        self.food.add_food(na::Point2::new(50.0,50.0),
-            na::Point2::new(WIDTH-50.0,100.0), TOTAL_FOOD as u32);
-    }
 }
 
 impl EventHandler for MainState {
     fn update(&mut self, ctx: &mut Context) -> GameResult {
-        let dt = ggez::timer::delta(ctx).as_secs_f32();
-        let ticks = ggez::timer::ticks(ctx);
-
-
-        for i in 0..self.home_pheromones.len() {
-            for j in 0..self.home_pheromones[0].len() {
-                self.home_pheromones[i][j] -= PHEROMONE_DECAY;
-                self.food_pheromones[i][j] -= PHEROMONE_DECAY;
-
-                if self.home_pheromones[i][j] < 0.0 {
-                    self.home_pheromones[i][j] = 0.0;
-                }
-                if self.food_pheromones[i][j] < 0.0 {
-                    self.food_pheromones[i][j] = 0.0;
-                }
-            }
-        }
-
-
-
-        // self.ants.target_food_position.par_iter_mut()
-        //     .zip(self.ants.target_food_index.par_iter_mut())
-        //     .zip(self.ants.state.par_iter_mut())
-        //     .zip(self.ants.velocity.par_iter_mut())
-        //     .zip(self.ants.desired_direction.par_iter_mut())
-        //     .for_each(|
-        //         ((((food_position,
-        //         food_index),
-        //         ant_state),
-        //         ant_velocity),
-        //         ant_desired_direction)
-        //     | {
-        //         if 
-        //     });
-
-
-
-        for i in 0..TOTAL_ANTS {
-            if self.ants.food_acquired(i) {
-                self.ants.state[i] = 2;
-                self.ants.set_antiparallel(i);
-                self.ants.target_food_position[i] = None;
-                match self.ants.target_food_index[i] {
-                    None => (),
-                    Some(j) => {
-                        self.food.state[j as usize] = 2;
-                        self.food.position[j as usize] = Some(
-                            na::Point2::new(WIDTH + 2000.0, HEIGHT + 2000.0));
-                    },
-                }
-            }
-
-
-            if self.ants.state[i] == 0 {
-                for j in 0..self.food.food_generated {
-                    match self.food.position[j as usize] {
-                        None => (),
-                        Some(k) => {
-                            if self.food.state[j as usize] == 0 && utils::distance(&k, &self.ants.position[i]) < ANT_VISION {
-                                self.food.state[j as usize] = 1;
-                                self.ants.set_food_target(i, k, j as u32);
-                                break;
-                            }
-                        }
-                    }
-                }
-            }
-
-            if self.home.touching_home(self.ants.position[i]) {
-                self.ants.state[i] = 0;
-                self.ants.set_antiparallel(i);
-            }
-
-
-            if ticks % 1 == 0 {
-                let approximate_y : usize = max(min(self.ants.position[i].y as usize, self.home_pheromones.len()-1), 0);
-                let approximate_x : usize = max(min(self.ants.position[i].x as usize, self.home_pheromones[0].len()-1), 0);
-
-                if self.ants.state[i] != 2 {
-                    self.home_pheromones[approximate_y][approximate_x] = 1.0;
-                } else {
-                    self.food_pheromones[approximate_y][approximate_x] = 1.0;
-                }
-            }
-
-            // Ant following pheromones algorithm
-
-            let (home_angle, food_angle) = utils::ant_rays(self.ants.position[i], self.ants.angle[i], &self.home_pheromones, &self.food_pheromones);
-            self.ants.set_pheromones_direction(i, home_angle, food_angle);
-        }
-
-        self.ants.update(dt);
-        Ok(())
+        // A fixed step keeps runs with the same seed identical.
+        let dt = self.session.world.config.dt.unwrap_or_else(|| ggez::timer::delta(ctx).as_secs_f32());
+        let profiler = self.session.world.profiler.clone();
+        let _span = profiler.span("update");
+        self.charts.record(&self.session.world);
+        self.session.step(dt)
     }
 
     fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        let profiler = self.session.world.profiler.clone();
+        let span = profiler.span("draw");
         graphics::clear(ctx, graphics::BLACK);
 
+        // terrain, rendered once
+        let version = self.session.world.terrain.version;
+        if self.terrain_image.as_ref().is_none_or(|(rendered, _)| *rendered != version) {
+            self.terrain_image = Some((version, graphics::Image::from_rgba8(ctx, WIDTH as u16, HEIGHT as u16,
+                &self.session.world.terrain.to_rgba())?));
+        }
+        if let Some((_, terrain_image)) = &self.terrain_image {
+            graphics::draw(ctx, terrain_image, graphics::DrawParam::new())?;
+        }
+
         let draw_param = graphics::DrawParam::default();
 
         // let mut ant_sprite_batch = graphics::spritebatch::SpriteBatch::new(
         //     graphics::Image::solid(ctx, ANT_RADIUS, graphics::WHITE).unwrap()
         // );
 
-        // for ant_index in 0..TOTAL_ANTS {
-        //     ant_sprite_batch.add(graphics::DrawParam::new().dest(self.ants.position[ant_index]));
+        // for ant_index in 0..self.session.world.ants.len() {
+        //     ant_sprite_batch.add(graphics::DrawParam::new().dest(self.session.world.ants.position[ant_index]));
         // }
 
         // graphics::draw(ctx, &ant_sprite_batch, draw_param).unwrap();
@@ -176,8 +78,8 @@ impl EventHandler for MainState {
             graphics::Image::solid(ctx, FOOD_RADIUS, graphics::Color::new(1.0, 0.0, 0.0, 1.0)).unwrap()
         );
 
-        for i in 0..self.food.food_generated {
-            match self.food.position[i as usize] {
+        for i in 0..self.session.world.food.food_generated {
+            match self.session.world.food.position[i as usize] {
                 None => (),
                 Some(j) => {
                     food_sprite_batch.add(graphics::DrawParam::new().dest(j));
@@ -193,77 +95,185 @@ impl EventHandler for MainState {
             graphics::Image::solid(ctx, FOOD_RADIUS, graphics::Color::new(0.0, 1.0, 1.0, 1.0)).unwrap()
         );
 
-        for y in 0..self.home_pheromones.len() {
-            for x in 0..self.home_pheromones[0].len() {
-                if self.home_pheromones[y][x] > 0.0 {
-                    pheromones_sprite_batch.add(
-                        graphics::DrawParam::new()
-                            .dest(na::Point2::new(x as f32, y as f32))
-                            .color(graphics::Color::new(0.0, 0.0, 1.0, self.home_pheromones[y][x]))
-                    );
-                }
-
-                if self.food_pheromones[y][x] > 0.0 {
-                    pheromones_sprite_batch.add(
-                        graphics::DrawParam::new()
-                            .dest(na::Point2::new(x as f32, y as f32))
-                            .color(graphics::Color::new(0.0, 1.0, 0.0, self.food_pheromones[y][x]))
-                    );
+        for layer in self.session.world.colonies.iter().flat_map(|colony| colony.pheromones.layers.iter()) {
+            for (y, row) in layer.grid.iter().enumerate() {
+                for (x, value) in row.iter().enumerate() {
+                    if *value > 0.0 {
+                        pheromones_sprite_batch.add(
+                            graphics::DrawParam::new()
+                                .dest(na::Point2::new(x as f32, y as f32))
+                                .color(graphics::Color::new(layer.color.r, layer.color.g, layer.color.b, *value))
+                        );
+                    }
                 }
             }
         }
 
         graphics::draw(ctx, &pheromones_sprite_batch, graphics::DrawParam::new()).unwrap();
 
-        // ant colony
-        let home_mesh = graphics::Mesh::new_circle(
-            ctx, graphics::DrawMode::stroke(1.0),
-            na::Point2::new(0.0,0.0),
-            self.home.radius,
-            graphics::FillOptions::DEFAULT_TOLERANCE,
-            graphics::Color::from_rgb(87, 67, 227)).unwrap();
+        // ant colonies
+        for colony in self.session.world.colonies.iter().filter(|colony| colony.active) {
+            let home_mesh = graphics::Mesh::new_circle(
+                ctx, graphics::DrawMode::stroke(1.0),
+                na::Point2::new(0.0,0.0),
+                colony.home.radius,
+                graphics::FillOptions::DEFAULT_TOLERANCE,
+                graphics::Color::from_rgb(87, 67, 227)).unwrap();
+
+            graphics::draw(ctx, &home_mesh, graphics::DrawParam::new().dest(colony.home.position)).unwrap();
+        }
+
+        for zone in self.session.world.config.danger_zones.iter() {
+            let danger_mesh = graphics::Mesh::new_circle(
+                ctx, graphics::DrawMode::stroke(1.0),
+                na::Point2::new(zone.x, zone.y),
+                zone.radius,
+                graphics::FillOptions::DEFAULT_TOLERANCE,
+                graphics::Color::new(1.0, 0.0, 0.0, 1.0)).unwrap();
 
-        graphics::draw(ctx, &home_mesh, graphics::DrawParam::new().dest(self.home.position)).unwrap();
+            graphics::draw(ctx, &danger_mesh, graphics::DrawParam::new()).unwrap();
+        }
+
+        for position in self.session.world.predators.position.iter() {
+            let predator_mesh = graphics::Mesh::new_circle(
+                ctx, graphics::DrawMode::fill(),
+                na::Point2::new(0.0, 0.0),
+                5.0,
+                graphics::FillOptions::DEFAULT_TOLERANCE,
+                graphics::Color::new(0.8, 0.2, 0.2, 1.0)).unwrap();
+
+            graphics::draw(ctx, &predator_mesh, graphics::DrawParam::new().dest(*position)).unwrap();
+        }
+
+        // per-caste statistics
+        let mut stats = String::new();
+        if !self.session.world.predators.position.is_empty() {
+            stats += &format!("predators  {} ants eaten  {} slain\n", self.session.world.predators.kills,
+                self.session.world.predators.deaths);
+        }
+        for (c, colony) in self.session.world.colonies.iter().enumerate() {
+            stats += &format!("colony {}  store {:.0}  delivered {}\n", c, colony.food_store, colony.food_delivered);
+            for (k, caste) in self.session.world.config.castes.iter().enumerate() {
+                let population = (0..self.session.world.ants.len())
+                    .filter(|&i| self.session.world.ants.colony[i] == c && self.session.world.ants.caste[i] == k)
+                    .count();
+                let caste_stats = &colony.caste_stats[k];
+                stats += &format!("  {:?}: {} alive  {} born  {} died ({} eaten)  {} delivered\n", caste.caste,
+                    population, caste_stats.births, caste_stats.deaths, caste_stats.killed, caste_stats.food_delivered);
+            }
+        }
+        let stats_text = graphics::Text::new(stats);
+        graphics::draw(ctx, &stats_text, graphics::DrawParam::new().dest(na::Point2::new(10.0, 10.0))).unwrap();
 
         // Ant vision for pheromone
 
-        // for i in 0..TOTAL_ANTS {
-        //     let angle = self.ants.angle[i];
+        // for i in 0..self.session.world.ants.len() {
+        //     let angle = self.session.world.ants.angle[i];
         //     let separation = 10.0;
         //     let vision_size = 20.0;
             
         //     let angles = [angle - PI/5.0, angle, angle + PI/5.0];
             
         //     for angle in angles.iter() {
-        //         let x_0 = self.ants.position[i].x + (separation + vision_size) * angle.cos() - vision_size / 2.0;
-        //         let y_0 = self.ants.position[i].y + (separation + vision_size) * angle.sin();
+        //         let x_0 = self.session.world.ants.position[i].x + (separation + vision_size) * angle.cos() - vision_size / 2.0;
+        //         let y_0 = self.session.world.ants.position[i].y + (separation + vision_size) * angle.sin();
                 
         //         let vision_box = graphics::Mesh::new_rectangle(ctx, graphics::DrawMode::stroke(1.0), graphics::Rect::new(x_0, y_0, vision_size, vision_size), graphics::WHITE).unwrap();
         //         graphics::draw(ctx, &vision_box, graphics::DrawParam::new()).unwrap();
         //     }
         // }
 
+        self.charts.draw(ctx, &self.session.world)?;
+
+        // average time per frame spent in every phase
+        if self.profile_visible {
+            let mut breakdown = String::from("ms per frame\n");
+            for (depth, name, ms) in profiler.breakdown() {
+                breakdown += &format!("{:indent$}{:<width$} {:6.2}\n", "", name, ms, indent = 2 * depth,
+                    width = 24 - 2 * depth);
+            }
+            let breakdown_text = graphics::Text::new(breakdown);
+            graphics::draw(ctx, &breakdown_text, graphics::DrawParam::new().dest(na::Point2::new(WIDTH - 260.0, 10.0)))?;
+        }
+
         // update the display
         graphics::present(ctx).unwrap();
+        drop(span);
+        profiler.end_frame();
         Ok(())
     }
+
+    fn key_down_event(&mut self, ctx: &mut Context, keycode: KeyCode, _keymods: KeyMods, _repeat: bool) {
+        match keycode {
+            KeyCode::C => self.charts.visible = !self.charts.visible,
+            KeyCode::P => {
+                // Profiles from the first time the breakdown is shown.
+                self.profile_visible = !self.profile_visible;
+                if self.profile_visible {
+                    self.session.world.profiler.set_enabled(true);
+                }
+            },
+            KeyCode::Escape => event::quit(ctx),
+            _ => (),
+        }
+    }
+
+    // Left click drops a pile of food, right click builds a piece of wall.
+    fn mouse_button_down_event(&mut self, _ctx: &mut Context, button: MouseButton, x: f32, y: f32) {
+        let event = match button {
+            MouseButton::Left => Event::AddFood {
+                name: "placed".to_string(), x: x - 15.0, y: y - 15.0, width: 30.0, height: 30.0, amount: 50,
+                path: Vec::new(), speed: 0.0,
+            },
+            MouseButton::Right => Event::AddWall { x: x - 10.0, y: y - 10.0, width: 20.0, height: 20.0 },
+            _ => return,
+        };
+        if let Err(e) = self.session.intervene(Intervention::Event(event)) {
+            println!("Error occured: {}", e);
+        }
+    }
+}
+
+// Prints the error with the usage and exits with a failure status.
+fn fail(e: impl std::fmt::Display) -> ! {
+    println!("{}\n{}", e, cli::USAGE);
+    process::exit(1);
 }
 
 fn main() {
+    let args = cli::Args::parse(env::args().skip(1)).unwrap_or_else(|e| fail(e));
+    let config = args.load_config().unwrap_or_else(|e| fail(e));
+    let result = if let Some(path) = &args.evolve {
+        cli::run_evolution(config, path)
+    } else if let Some(path) = &args.tune {
+        cli::run_tuning(config, path)
+    } else if let Some(path) = &args.sweep {
+        cli::run_sweep(config, path)
+    } else {
+        args.session(config).and_then(|session| match args.headless {
+            Some(ticks) => cli::run_headless(session, ticks),
+            None => run_window(session),
+        })
+    };
+    if let Err(e) = result {
+        fail(e);
+    }
+}
+
+fn run_window(session: Session) -> GameResult {
     let window_mode = conf::WindowMode::default()
         .dimensions(WIDTH, HEIGHT);
 
     let cb = ContextBuilder::new("Ant Simulation", "Some One")
         .window_mode(window_mode);
-    let (mut ctx, mut event_loop) = cb.build().unwrap();
+    let (mut ctx, mut event_loop) = cb.build()?;
 
     graphics::set_window_title(&ctx, "Ant Simulation");
 
-    let mut state = MainState::new(&mut ctx);
-    state.initilize_positions();
-
+    let mut state = MainState::new(session);
     match event::run(&mut ctx, &mut event_loop, &mut state) {
         Ok(_) => println!("Exited cleanly."),
         Err(e) => println!("Error occured: {}", e)
     }
+    state.session.finish()
 }
@@ -0,0 +1,192 @@
+use ggez::{GameError, GameResult};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use crate::brain::{RETURN, SEARCH, TARGET};
+use crate::config::Config;
+use crate::world::World;
+
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MetricsFormat {
+    Csv,
+    /// One JSON object per line.
+    Jsonl,
+}
+
+/// Where and how often to record metrics. Nothing is recorded without a `path`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MetricsConfig {
+    pub path: Option<String>,
+    pub format: MetricsFormat,
+    /// Ticks between two samples.
+    pub interval: u64,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        MetricsConfig {
+            path: None,
+            format: MetricsFormat::Csv,
+            interval: 60,
+        }
+    }
+}
+
+/// The state of the world at one tick, summed over all colonies.
+#[derive(Clone, Debug)]
+pub struct Sample {
+    pub tick: u64,
+    pub searching: usize,
+    pub targeting: usize,
+    pub returning: usize,
+    pub food_left: usize,
+    /// Pellets delivered since the start.
+    pub delivered: u64,
+    /// Total concentration on every pheromone layer.
+    pub pheromones: Vec<f64>,
+    /// Mean distance walked on the trips that brought food home since the
+    /// previous sample, if there were any.
+    pub mean_trip: Option<f64>,
+}
+
+/// Takes samples, remembering the trips counted so far.
+#[derive(Default)]
+pub struct Sampler {
+    trips: u64,
+    trip_distance: f64,
+}
+
+impl Sampler {
+    pub fn sample(&mut self, world: &World) -> Sample {
+        let count = |state: u32| world.ants.state.iter().filter(|s| **s == state).count();
+        let mut pheromones = vec![0.0; world.config.pheromones.len()];
+        for colony in world.colonies.iter() {
+            for (l, layer) in colony.pheromones.layers.iter().enumerate() {
+                pheromones[l] += layer.grid.iter().flatten().map(|value| *value as f64).sum::<f64>();
+            }
+        }
+
+        let trips: u64 = world.colonies.iter().map(|colony| colony.trips).sum();
+        let trip_distance: f64 = world.colonies.iter().map(|colony| colony.trip_distance).sum();
+        let mean_trip = if trips > self.trips {
+            Some((trip_distance - self.trip_distance) / (trips - self.trips) as f64)
+        } else {
+            None
+        };
+        self.trips = trips;
+        self.trip_distance = trip_distance;
+
+        Sample {
+            tick: world.tick,
+            searching: count(SEARCH),
+            targeting: count(TARGET),
+            returning: count(RETURN),
+            food_left: world.food.position.iter().filter(|position| position.is_some()).count(),
+            delivered: world.colonies.iter().map(|colony| colony.food_delivered).sum(),
+            pheromones,
+            mean_trip,
+        }
+    }
+}
+
+/// Writes a sample every `interval` ticks to a CSV or JSON Lines file.
+pub struct Metrics {
+    config: MetricsConfig,
+    layers: Vec<String>,
+    sampler: Sampler,
+    writer: BufWriter<File>,
+}
+
+impl Metrics {
+    pub fn create(path: &str, config: &Config) -> GameResult<Self> {
+        let file = File::create(path).map_err(|e| GameError::ConfigError(format!("could not create {}: {}", path, e)))?;
+        let mut metrics = Metrics {
+            config: config.metrics.clone(),
+            layers: config.pheromones.iter().map(|layer| layer.name.clone()).collect(),
+            sampler: Sampler::default(),
+            writer: BufWriter::new(file),
+        };
+        if let MetricsFormat::Csv = metrics.config.format {
+            let mut header = String::from("tick,searching,targeting,returning,food_left,delivered");
+            for layer in metrics.layers.iter() {
+                header += &format!(",pheromone_{}", layer);
+            }
+            header += ",mean_trip\n";
+            metrics.write(&header)?;
+        }
+        Ok(metrics)
+    }
+
+    /// Samples the world if a sample is due at its tick.
+    pub fn record(&mut self, world: &World) -> GameResult {
+        if !world.tick.is_multiple_of(self.config.interval.max(1)) {
+            return Ok(());
+        }
+
+        let sample = self.sampler.sample(world);
+        let line = match self.config.format {
+            MetricsFormat::Csv => {
+                let mut line = format!("{},{},{},{},{},{}", sample.tick, sample.searching, sample.targeting,
+                    sample.returning, sample.food_left, sample.delivered);
+                for mass in sample.pheromones.iter() {
+                    line += &format!(",{}", mass);
+                }
+                line += &format!(",{}\n", sample.mean_trip.map(|trip| trip.to_string()).unwrap_or_default());
+                line
+            },
+            MetricsFormat::Jsonl => {
+                let mut line = format!(
+                    "{{\"tick\":{},\"searching\":{},\"targeting\":{},\"returning\":{},\"food_left\":{},\"delivered\":{}",
+                    sample.tick, sample.searching, sample.targeting, sample.returning, sample.food_left,
+                    sample.delivered);
+                line += ",\"pheromones\":{";
+                let masses: Vec<String> = self.layers.iter().zip(sample.pheromones.iter())
+                    .map(|(layer, mass)| format!("\"{}\":{}", escape(layer), mass))
+                    .collect();
+                line += &masses.join(",");
+                line += &format!("}},\"mean_trip\":{}}}\n",
+                    sample.mean_trip.map(|trip| trip.to_string()).unwrap_or_else(|| "null".to_string()));
+                line
+            },
+        };
+        self.write(&line)
+    }
+
+    // Flushed line by line so the file can be watched while the simulation runs.
+    fn write(&mut self, text: &str) -> GameResult {
+        self.writer.write_all(text.as_bytes()).and_then(|_| self.writer.flush())
+            .map_err(|e| GameError::ConfigError(format!("could not write metrics: {}", e)))
+    }
+}
+
+// Quotes, backslashes and control characters escaped for a JSON string.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layer_names_are_escaped() {
+        assert_eq!(escape("food"), "food");
+        assert_eq!(escape("a \"b\" \\ c"), "a \\\"b\\\" \\\\ c");
+        assert_eq!(escape("line\nend\u{1}"), "line\\nend\\u0001");
+    }
+}
@@ -0,0 +1,158 @@
+use ggez::nalgebra as na;
+use ggez::{GameError, GameResult};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::f32::consts::FRAC_PI_2;
+use std::fs;
+
+use crate::brain::{AntBrain, Decision, Mark, Perception, RETURN, SEARCH, TARGET};
+use crate::sensor::BoxSensor;
+use crate::WIDTH;
+
+/// A small fully connected network with tanh activations.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Network {
+    /// Neurons per layer, inputs first and outputs last.
+    pub sizes: Vec<usize>,
+    /// Every layer row by row, each row ending with its bias.
+    pub weights: Vec<f32>,
+}
+
+impl Network {
+    pub fn random(sizes: &[usize], rng: &mut impl Rng) -> Self {
+        let count = sizes.windows(2).map(|pair| (pair[0] + 1) * pair[1]).sum();
+        Network {
+            sizes: sizes.to_vec(),
+            weights: (0..count).map(|_| rng.gen_range(-1.0..1.0)).collect(),
+        }
+    }
+
+    pub fn load(path: &str) -> GameResult<Self> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| GameError::ConfigError(format!("could not read {}: {}", path, e)))?;
+        let network: Network = toml::from_str(&contents)
+            .map_err(|e| GameError::ConfigError(format!("could not parse {}: {}", path, e)))?;
+        let count: usize = network.sizes.windows(2).map(|pair| (pair[0] + 1) * pair[1]).sum();
+        if network.sizes.len() < 2 || network.weights.len() != count {
+            return Err(GameError::ConfigError(format!("{} has {} weights, its layer sizes need {}", path,
+                network.weights.len(), count)));
+        }
+        Ok(network)
+    }
+
+    pub fn save(&self, path: &str) -> GameResult {
+        let contents = toml::to_string(self)
+            .map_err(|e| GameError::ConfigError(format!("could not serialize network: {}", e)))?;
+        fs::write(path, contents).map_err(|e| GameError::ConfigError(format!("could not write {}: {}", path, e)))
+    }
+
+    pub fn inputs(&self) -> usize {
+        self.sizes[0]
+    }
+
+    pub fn outputs(&self) -> usize {
+        self.sizes[self.sizes.len() - 1]
+    }
+
+    pub fn forward(&self, inputs: &[f32]) -> Vec<f32> {
+        let mut values = inputs.to_vec();
+        let mut weights = self.weights.iter();
+        for &size in self.sizes[1..].iter() {
+            values = (0..size)
+                .map(|_| {
+                    let sum: f32 = values.iter().map(|value| value * weights.next().unwrap()).sum();
+                    (sum + weights.next().unwrap()).tanh()
+                })
+                .collect();
+        }
+        values
+    }
+}
+
+/// Inputs a neural brain needs with `layers` pheromone layers: the scores of
+/// the three vision boxes for every layer, the state, the nest and targeted
+/// pellet relative to the heading, and the load.
+pub fn input_size(layers: usize) -> usize {
+    layers * 3 + 10
+}
+
+/// One turn output followed by one deposit output per pheromone layer.
+pub fn output_size(layers: usize) -> usize {
+    layers + 1
+}
+
+/// Steers and lays pheromone as its network says. Picking up and dropping
+/// food happen on their own, like for the random brain. Pheromone is smelt
+/// through the default three boxes whatever the colony's sensor.
+pub struct NeuralBrain {
+    network: Network,
+    sensor: BoxSensor,
+}
+
+impl NeuralBrain {
+    pub fn new(network: Network, layers: usize) -> GameResult<Self> {
+        if network.inputs() != input_size(layers) || network.outputs() != output_size(layers) {
+            return Err(GameError::ConfigError(format!(
+                "a network for {} pheromone layers needs {} inputs and {} outputs, got {} and {}",
+                layers, input_size(layers), output_size(layers), network.inputs(), network.outputs())));
+        }
+        Ok(NeuralBrain { network, sensor: BoxSensor::default() })
+    }
+
+    fn inputs(&self, p: &Perception) -> Vec<f32> {
+        let heading = p.heading.y.atan2(p.heading.x);
+        let relative = |direction: na::Vector2<f32>| {
+            let angle = direction.y.atan2(direction.x) - heading;
+            [angle.cos(), angle.sin()]
+        };
+
+        let mut inputs = Vec::with_capacity(self.network.inputs());
+        for layer in p.field.layers.iter() {
+            inputs.extend(self.sensor.scores(p.position, p.angle, layer).iter().map(|score| score.tanh()));
+        }
+        for state in [SEARCH, TARGET, RETURN].iter() {
+            inputs.push((p.state == *state) as u8 as f32);
+        }
+        inputs.extend(&relative(p.home - p.position));
+        inputs.push(na::distance(&p.home, &p.position) / WIDTH);
+        match p.target_food {
+            Some(food) => {
+                inputs.push(1.0);
+                inputs.extend(&relative(food - p.position));
+            },
+            None => inputs.extend(&[0.0, 0.0, 0.0]),
+        }
+        inputs.push(p.carried as f32 / p.caste.carrying_capacity.max(1) as f32);
+        inputs
+    }
+}
+
+impl AntBrain for NeuralBrain {
+    fn decide(&self, p: &Perception) -> Decision {
+        let outputs = self.network.forward(&self.inputs(p));
+
+        let full = p.food_reached && p.carried + 1 >= p.caste.carrying_capacity;
+        let state = if p.at_home {
+            SEARCH
+        } else if full {
+            RETURN
+        } else if p.food_reached {
+            SEARCH
+        } else {
+            p.state
+        };
+
+        let marks = outputs[1..].iter().enumerate()
+            .filter(|(_, output)| **output > 0.0)
+            .map(|(layer, _)| Mark::Cell(layer))
+            .collect();
+
+        Decision {
+            direction: na::Rotation2::new(outputs[0] * FRAC_PI_2) * p.heading,
+            state,
+            pick_up: p.food_reached,
+            drop: p.at_home,
+            marks,
+        }
+    }
+}
@@ -0,0 +1,270 @@
+use ggez::graphics;
+use rayon::prelude::*;
+
+use crate::boundary::BoundaryMode;
+use crate::config::PheromoneConfig;
+use crate::terrain::Terrain;
+
+pub const HOME: &str = "home";
+pub const FOOD: &str = "food";
+pub const ALARM: &str = "alarm";
+pub const REPELLENT: &str = "repellent";
+pub const RECRUITMENT: &str = "recruitment";
+pub const DANGER: &str = "danger";
+
+pub struct PheromoneLayer {
+    pub decay: f32,
+    pub diffusion: f32,
+    pub color: graphics::Color,
+    pub grid: Vec<Vec<f32>>,
+    boundary: BoundaryMode,
+}
+
+/// Indices of the layers the ant behaviours know about. A layer that is not
+/// declared in the config is `None` and the behaviour using it is skipped.
+#[derive(Copy, Clone, Default)]
+pub struct LayerIds {
+    pub home: Option<usize>,
+    pub food: Option<usize>,
+    pub alarm: Option<usize>,
+    pub repellent: Option<usize>,
+    pub recruitment: Option<usize>,
+    pub danger: Option<usize>,
+}
+
+pub struct PheromoneField {
+    pub layers: Vec<PheromoneLayer>,
+    pub ids: LayerIds,
+}
+
+impl PheromoneLayer {
+    pub fn new(config: &PheromoneConfig, width: usize, height: usize, boundary: BoundaryMode) -> Self {
+        PheromoneLayer {
+            decay: config.decay,
+            diffusion: config.diffusion,
+            color: graphics::Color::new(config.color[0], config.color[1], config.color[2], 1.0),
+            grid: vec![vec![0.0; width]; height],
+            boundary,
+        }
+    }
+
+    /// Diffuses, then evaporates every cell at the rate of the terrain under it.
+    pub fn update(&mut self, terrain: &Terrain) {
+        if self.diffusion > 0.0 {
+            self.diffuse();
+        }
+
+        let decay = self.decay;
+        self.grid.par_iter_mut().zip(terrain.evaporation.par_iter()).for_each(|(row, evaporation)| {
+            for (value, evaporation) in row.iter_mut().zip(evaporation.iter()) {
+                *value = (*value - decay * evaporation).max(0.0);
+            }
+        });
+    }
+
+    // Blend every cell with the mean of its 3x3 neighbourhood. Neighbours
+    // past the edge are on the other side when the world wraps around, and
+    // count as the cell itself otherwise, so no pheromone is made or lost at
+    // the edges.
+    fn diffuse(&mut self) {
+        let diffusion = self.diffusion;
+        let wrap = self.boundary == BoundaryMode::Wrap;
+        let source = &self.grid;
+        let height = source.len();
+        let width = source[0].len();
+
+        let diffused: Vec<Vec<f32>> = (0..height).into_par_iter().map(|y| {
+            (0..width).map(|x| {
+                let mut sum = 0.0;
+                for j in y as i64 - 1..=y as i64 + 1 {
+                    for i in x as i64 - 1..=x as i64 + 1 {
+                        let inside = j >= 0 && j < height as i64 && i >= 0 && i < width as i64;
+                        sum += if wrap {
+                            source[j.rem_euclid(height as i64) as usize][i.rem_euclid(width as i64) as usize]
+                        } else if inside {
+                            source[j as usize][i as usize]
+                        } else {
+                            source[y][x]
+                        };
+                    }
+                }
+                source[y][x] * (1.0 - diffusion) + (sum / 9.0) * diffusion
+            }).collect()
+        }).collect();
+
+        self.grid = diffused;
+    }
+
+    fn cell(&self, x: f32, y: f32) -> Option<(usize, usize)> {
+        let x = self.boundary.cell(x, self.grid[0].len())?;
+        let y = self.boundary.cell(y, self.grid.len())?;
+        Some((x, y))
+    }
+
+    /// Concentration at a point. Outside of the grid it is zero, unless the
+    /// world wraps around.
+    pub fn value(&self, x: f32, y: f32) -> f32 {
+        match self.cell(x, y) {
+            Some((x, y)) => self.grid[y][x],
+            None => 0.0,
+        }
+    }
+
+    /// Total concentration over the cells of the box `[x_0, x_1) x [y_0, y_1)`.
+    pub fn box_sum(&self, x_0: f32, y_0: f32, x_1: f32, y_1: f32) -> f32 {
+        let mut score = 0.0;
+        let (x_0, y_0) = (x_0.floor(), y_0.floor());
+        for j in 0..(y_1.floor() - y_0).max(0.0) as usize {
+            for i in 0..(x_1.floor() - x_0).max(0.0) as usize {
+                score += self.value(x_0 + i as f32, y_0 + j as f32);
+            }
+        }
+        score
+    }
+
+    pub fn deposit(&mut self, x: usize, y: usize, amount: f32) {
+        if y < self.grid.len() && x < self.grid[0].len() {
+            self.grid[y][x] = self.grid[y][x].max(amount);
+        }
+    }
+
+    pub fn deposit_disk(&mut self, x: f32, y: f32, radius: f32, amount: f32) {
+        let steps = radius.ceil() as i32;
+
+        for j in -steps..=steps {
+            for i in -steps..=steps {
+                if (i * i + j * j) as f32 <= radius * radius {
+                    if let Some((cell_x, cell_y)) = self.cell(x + i as f32, y + j as f32) {
+                        self.deposit(cell_x, cell_y, amount);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl LayerIds {
+    pub fn new(configs: &[PheromoneConfig]) -> Self {
+        let find = |name: &str| configs.iter().position(|config| config.name == name);
+        LayerIds {
+            home: find(HOME),
+            food: find(FOOD),
+            alarm: find(ALARM),
+            repellent: find(REPELLENT),
+            recruitment: find(RECRUITMENT),
+            danger: find(DANGER),
+        }
+    }
+}
+
+impl PheromoneField {
+    pub fn new(configs: &[PheromoneConfig], width: usize, height: usize, boundary: BoundaryMode) -> Self {
+        PheromoneField {
+            layers: configs.iter().map(|config| PheromoneLayer::new(config, width, height, boundary)).collect(),
+            ids: LayerIds::new(configs),
+        }
+    }
+
+    pub fn update(&mut self, terrain: &Terrain) {
+        for layer in self.layers.iter_mut() {
+            layer.update(terrain);
+        }
+    }
+
+    pub fn deposit(&mut self, layer: Option<usize>, x: usize, y: usize, amount: f32) {
+        if let Some(layer) = layer {
+            self.layers[layer].deposit(x, y, amount);
+        }
+    }
+
+    /// Washes away `strength` of every layer, as rain does.
+    pub fn wash(&mut self, strength: f32) {
+        let keep = (1.0 - strength).max(0.0);
+        for layer in self.layers.iter_mut() {
+            layer.grid.par_iter_mut().for_each(|row| {
+                for value in row.iter_mut() {
+                    *value *= keep;
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain::TerrainConfig;
+
+    const WIDTH: usize = 40;
+    const HEIGHT: usize = 30;
+
+    fn layer(decay: f32, diffusion: f32) -> PheromoneLayer {
+        wrapping(decay, diffusion, BoundaryMode::Reflect)
+    }
+
+    fn wrapping(decay: f32, diffusion: f32, boundary: BoundaryMode) -> PheromoneLayer {
+        let config = PheromoneConfig { name: FOOD.to_string(), decay, diffusion, color: [1.0, 1.0, 1.0] };
+        PheromoneLayer::new(&config, WIDTH, HEIGHT, boundary)
+    }
+
+    fn mass(layer: &PheromoneLayer) -> f32 {
+        layer.grid.iter().flatten().sum()
+    }
+
+    #[test]
+    fn diffusion_keeps_the_mass() {
+        let terrain = Terrain::new(&TerrainConfig::default(), WIDTH, HEIGHT).unwrap();
+        let mut layer = layer(0.0, 0.5);
+        layer.deposit_disk(20.0, 15.0, 3.0, 10.0);
+        // Right against the edges and in a corner too.
+        layer.deposit_disk(0.0, 10.0, 2.0, 10.0);
+        layer.deposit(WIDTH - 1, HEIGHT - 1, 10.0);
+        let before = mass(&layer);
+        for _ in 0..20 {
+            layer.update(&terrain);
+        }
+        assert!((mass(&layer) - before).abs() < before * 1e-4);
+        assert!(layer.grid[15][20] < 10.0);
+        assert!(layer.grid[15][25] > 0.0);
+    }
+
+    #[test]
+    fn diffusion_wraps_around() {
+        let terrain = Terrain::new(&TerrainConfig::default(), WIDTH, HEIGHT).unwrap();
+        let mut layer = wrapping(0.0, 0.5, BoundaryMode::Wrap);
+        layer.deposit(0, 10, 10.0);
+        layer.update(&terrain);
+        assert!(layer.grid[10][WIDTH - 1] > 0.0);
+        assert!((mass(&layer) - 10.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn decay_shrinks_the_mass() {
+        let terrain = Terrain::new(&TerrainConfig::default(), WIDTH, HEIGHT).unwrap();
+        let mut layer = layer(0.5, 0.2);
+        layer.deposit_disk(20.0, 15.0, 3.0, 10.0);
+        let mut last = mass(&layer);
+        for _ in 0..10 {
+            layer.update(&terrain);
+            let mass = mass(&layer);
+            assert!(mass < last);
+            last = mass;
+        }
+        assert!(layer.grid.iter().flatten().all(|value| *value >= 0.0));
+    }
+
+    #[test]
+    fn disks_stay_in_the_grid() {
+        let mut layer = layer(0.0, 0.0);
+        layer.deposit_disk(-2.0, -2.0, 5.0, 1.0);
+        layer.deposit_disk(WIDTH as f32 + 1.0, HEIGHT as f32 - 1.0, 5.0, 1.0);
+        layer.deposit_disk(1000.0, 1000.0, 5.0, 1.0);
+        assert_eq!(layer.grid.len(), HEIGHT);
+        assert!(layer.grid.iter().all(|row| row.len() == WIDTH));
+        assert_eq!(layer.grid[0][0], 1.0);
+        assert_eq!(layer.grid[HEIGHT - 1][WIDTH - 1], 1.0);
+        assert_eq!(layer.grid[HEIGHT / 2][WIDTH / 2], 0.0);
+        // The part of a disk past the edge is dropped, not folded back in.
+        assert_eq!(layer.grid[0][WIDTH - 1], 0.0);
+    }
+}
@@ -0,0 +1,196 @@
+use ggez::nalgebra as na;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+
+use crate::boundary::BoundaryMode;
+use crate::config::PredatorConfig;
+use crate::spatial::SpatialHash;
+use crate::terrain::Terrain;
+use crate::utils;
+
+/// Spiders and the like. Stored like the ants, one vector per attribute.
+pub struct Predator {
+    pub position: Vec<na::Point2<f32>>,
+    pub velocity: Vec<na::Vector2<f32>>,
+    pub health: Vec<f32>,
+    // Seconds left before the predator hunts again.
+    digesting: Vec<f32>,
+    pub kills: u64,
+    pub deaths: u64,
+    pub config: PredatorConfig,
+    window_size: (f32, f32),
+    rng: StdRng,
+}
+
+impl Predator {
+    pub fn new(config: &PredatorConfig, screen_w: f32, screen_h: f32, seed: u64) -> Self {
+        let mut predator = Predator {
+            position: Vec::new(),
+            velocity: Vec::new(),
+            health: Vec::new(),
+            digesting: Vec::new(),
+            kills: 0,
+            deaths: 0,
+            config: config.clone(),
+            window_size: (screen_w, screen_h),
+            rng: StdRng::seed_from_u64(seed),
+        };
+
+        for _ in 0..config.count {
+            let position = na::Point2::new(predator.rng.gen_range(0.0..screen_w), predator.rng.gen_range(0.0..screen_h));
+            predator.spawn(position);
+        }
+        predator
+    }
+
+    fn spawn(&mut self, position: na::Point2<f32>) {
+        let angle: f32 = self.rng.gen_range(0.0..std::f32::consts::TAU);
+        self.position.push(position);
+        self.velocity.push(na::Vector2::new(angle.cos(), angle.sin()) * self.config.speed);
+        self.health.push(self.config.health);
+        self.digesting.push(0.0);
+    }
+
+    // A slain predator is replaced by a fresh one entering from a random edge.
+    fn respawn(&mut self, index: usize) {
+        let (w, h) = self.window_size;
+        let position = match self.rng.gen_range(0..4) {
+            0 => na::Point2::new(self.rng.gen_range(0.0..w), 0.0),
+            1 => na::Point2::new(self.rng.gen_range(0.0..w), h),
+            2 => na::Point2::new(0.0, self.rng.gen_range(0.0..h)),
+            _ => na::Point2::new(w, self.rng.gen_range(0.0..h)),
+        };
+        self.position[index] = position;
+        self.health[index] = self.config.health;
+        self.digesting[index] = 0.0;
+        self.deaths += 1;
+    }
+
+    /// Nearest predator within `radius`, if any.
+    pub fn nearest(&self, position: &na::Point2<f32>, radius: f32) -> Option<na::Point2<f32>> {
+        self.position.iter()
+            .filter(|predator| utils::distance(predator, position) < radius)
+            .min_by(|a, b| utils::distance(a, position).total_cmp(&utils::distance(b, position)))
+            .copied()
+    }
+
+    /// Moves every predator, lets soldiers in reach wound it and returns the
+    /// indices of the ants that were eaten this tick. `ant_index` has to be
+    /// built from `ants` as they are now.
+    pub fn update(&mut self, dt: f32, ants: &[na::Point2<f32>], ant_index: &SpatialHash,
+        boundary: BoundaryMode, terrain: &Terrain, is_soldier: impl Fn(usize) -> bool) -> Vec<usize> {
+        // Predators cannot escape, they bounce off the edge instead.
+        let boundary = if boundary == BoundaryMode::Respawn { BoundaryMode::Reflect } else { boundary };
+        let config = &self.config;
+        let mut killed = Vec::new();
+
+        for p in 0..self.position.len() {
+            let position = self.position[p];
+
+            let mut soldiers = 0;
+            ant_index.query(&position, config.kill_radius, |i| {
+                if is_soldier(i as usize) && utils::distance(&ants[i as usize], &position) < config.kill_radius {
+                    soldiers += 1;
+                }
+            });
+            self.health[p] -= soldiers as f32 * config.soldier_damage * dt;
+
+            let prey = if self.digesting[p] > 0.0 {
+                self.digesting[p] -= dt;
+                None
+            } else {
+                ant_index.nearest(&position, config.hunt_radius, |i| {
+                    if killed.contains(&(i as usize)) { None } else { ants.get(i as usize).copied() }
+                })
+            };
+
+            let mut desired = match prey {
+                Some(i) => {
+                    let target = ants[i as usize];
+                    if utils::distance(&target, &position) < config.kill_radius {
+                        killed.push(i as usize);
+                        self.digesting[p] = config.digest_time;
+                        self.kills += 1;
+                    }
+                    (target - position).try_normalize(f32::EPSILON).unwrap_or_else(na::zero) * config.chase_speed
+                },
+                None => {
+                    let random_unit_vector = na::Vector2::new(self.rng.gen_range(-1.0..1.0), self.rng.gen_range(-1.0..1.0));
+                    let heading = self.velocity[p].try_normalize(f32::EPSILON).unwrap_or(random_unit_vector);
+                    (heading + random_unit_vector * 0.2).normalize() * config.speed
+                },
+            };
+
+            desired *= terrain.speed(&position).max(0.1);
+            if !terrain.passable(&(position + desired * dt)) {
+                desired = -desired;
+            }
+            self.velocity[p] = desired;
+            self.position[p] += desired * dt;
+            let mut direction = desired;
+            boundary.apply(self.window_size, &mut self.position[p], &mut self.velocity[p], &mut direction);
+        }
+
+        for p in 0..self.position.len() {
+            if self.health[p] <= 0.0 {
+                self.respawn(p);
+            }
+        }
+
+        killed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain::TerrainConfig;
+
+    const SIZE: f32 = 200.0;
+
+    // One tick of a predator in the middle of the world, with ants at `ants`.
+    fn hunt(config: PredatorConfig, ants: &[na::Point2<f32>], soldiers: bool) -> (Predator, Vec<usize>) {
+        let config = PredatorConfig { count: 1, ..config };
+        let mut predator = Predator::new(&config, SIZE, SIZE, 1);
+        predator.position[0] = na::Point2::new(100.0, 100.0);
+        let terrain = Terrain::new(&TerrainConfig::default(), SIZE as usize, SIZE as usize).unwrap();
+        let mut index = SpatialHash::new(SIZE, SIZE, 50.0);
+        index.rebuild(ants.iter());
+        let killed = predator.update(0.1, ants, &index, BoundaryMode::Reflect, &terrain, |_| soldiers);
+        (predator, killed)
+    }
+
+    #[test]
+    fn ants_in_reach_are_eaten() {
+        let ants = [na::Point2::new(180.0, 180.0), na::Point2::new(103.0, 100.0)];
+        let (predator, killed) = hunt(PredatorConfig::default(), &ants, false);
+        assert_eq!(killed, vec![1]);
+        assert_eq!(predator.kills, 1);
+        assert!(predator.digesting[0] > 0.0);
+        assert_eq!(predator.health[0], predator.config.health);
+    }
+
+    #[test]
+    fn ants_out_of_reach_are_chased() {
+        let ants = [na::Point2::new(150.0, 100.0)];
+        let (predator, killed) = hunt(PredatorConfig::default(), &ants, false);
+        assert!(killed.is_empty());
+        assert!(predator.position[0].x > 100.0);
+    }
+
+    #[test]
+    fn soldiers_wound_the_predator() {
+        let ants = [na::Point2::new(103.0, 100.0), na::Point2::new(100.0, 103.0)];
+        let (predator, _) = hunt(PredatorConfig::default(), &ants, true);
+        let damage = 2.0 * predator.config.soldier_damage * 0.1;
+        assert!((predator.health[0] - (predator.config.health - damage)).abs() < 1e-4);
+    }
+
+    #[test]
+    fn slain_predators_respawn() {
+        let ants = [na::Point2::new(103.0, 100.0)];
+        let (predator, _) = hunt(PredatorConfig { health: 0.5, ..PredatorConfig::default() }, &ants, true);
+        assert_eq!(predator.deaths, 1);
+        assert_eq!(predator.health[0], 0.5);
+    }
+}
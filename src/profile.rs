@@ -0,0 +1,187 @@
+use ggez::{GameError, GameResult};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Where to write the profile. With a `path`, every run is profiled and
+/// `<path>.folded` (collapsed stacks in microseconds) and `<path>.svg` (a
+/// flamegraph) are written when it ends. In the window `P` shows the time
+/// spent per phase, profiling from then on even without a `path`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProfileConfig {
+    pub path: Option<String>,
+}
+
+// Weight of the latest frame in the averages shown on screen.
+const SMOOTHING: f32 = 0.1;
+
+#[derive(Default)]
+struct State {
+    enabled: bool,
+    // Spans not closed yet: name, start and time spent in spans inside it.
+    open: Vec<(&'static str, Instant, Duration)>,
+    // Time spent in every stack of spans itself, outside of the spans inside
+    // it, since profiling started. Keys are names joined by `;`.
+    totals: BTreeMap<String, Duration>,
+    // Time spent in every stack including the spans inside it, this frame
+    // and averaged over recent frames, in milliseconds.
+    frame: BTreeMap<String, Duration>,
+    average: BTreeMap<String, f32>,
+}
+
+/// Times named, nested spans of work, like the phases of a tick. Clones share
+/// their timings, and spans cost nothing while profiling is off.
+#[derive(Clone, Default)]
+pub struct Profiler {
+    state: Arc<Mutex<State>>,
+}
+
+impl Profiler {
+    pub fn enabled(&self) -> bool {
+        self.state.lock().unwrap().enabled
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        self.state.lock().unwrap().enabled = enabled;
+    }
+
+    /// Times everything until the returned span is dropped, inside the spans
+    /// open at the moment.
+    pub fn span(&self, name: &'static str) -> Span {
+        let mut state = self.state.lock().unwrap();
+        if !state.enabled {
+            return Span { state: None };
+        }
+        state.open.push((name, Instant::now(), Duration::default()));
+        Span { state: Some(self.state.clone()) }
+    }
+
+    /// Folds the timings of the frame that just ended into the averages.
+    pub fn end_frame(&self) {
+        let mut state = self.state.lock().unwrap();
+        let frame = std::mem::take(&mut state.frame);
+        for average in state.average.values_mut() {
+            *average *= 1.0 - SMOOTHING;
+        }
+        for (stack, time) in frame {
+            *state.average.entry(stack).or_insert(0.0) += time.as_secs_f32() * 1000.0 * SMOOTHING;
+        }
+    }
+
+    /// Average milliseconds per frame of every stack, as its depth and the
+    /// name of its innermost span, with spans after the spans they are in.
+    pub fn breakdown(&self) -> Vec<(usize, String, f32)> {
+        let state = self.state.lock().unwrap();
+        state.average.iter()
+            .map(|(stack, ms)| {
+                let depth = stack.matches(';').count();
+                (depth, stack.rsplit(';').next().unwrap().to_string(), *ms)
+            })
+            .collect()
+    }
+
+    /// One `stack microseconds` line per stack of spans.
+    pub fn collapsed(&self) -> Vec<String> {
+        let state = self.state.lock().unwrap();
+        state.totals.iter()
+            .map(|(stack, time)| format!("{} {}", stack, time.as_micros()))
+            .filter(|line| !line.ends_with(" 0"))
+            .collect()
+    }
+
+    /// Writes `<path>.folded` and `<path>.svg`.
+    pub fn save(&self, path: &str) -> GameResult {
+        let lines = self.collapsed();
+        let folded = format!("{}.folded", path);
+        fs::write(&folded, lines.join("\n") + "\n").map_err(|e| error(&folded, e))?;
+
+        let svg = format!("{}.svg", path);
+        let file = File::create(&svg).map_err(|e| error(&svg, e))?;
+        let mut options = inferno::flamegraph::Options::default();
+        options.title = "ant-simulation".to_string();
+        options.count_name = "μs".to_string();
+        inferno::flamegraph::from_lines(&mut options, lines.iter().map(|line| line.as_str()), BufWriter::new(file))
+            .map_err(|e| error(&svg, e))
+    }
+}
+
+/// A span being timed, see `Profiler::span`.
+pub struct Span {
+    state: Option<Arc<Mutex<State>>>,
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        let state = match &self.state {
+            Some(state) => state,
+            None => return,
+        };
+        let mut state = state.lock().unwrap();
+        let (name, start, inner) = match state.open.pop() {
+            Some(span) => span,
+            None => return,
+        };
+        let elapsed = start.elapsed();
+        let stack = state.open.iter().map(|(name, _, _)| *name).chain(std::iter::once(name))
+            .collect::<Vec<_>>()
+            .join(";");
+        *state.totals.entry(stack.clone()).or_default() += elapsed.saturating_sub(inner);
+        *state.frame.entry(stack).or_default() += elapsed;
+        if let Some(parent) = state.open.last_mut() {
+            parent.2 += elapsed;
+        }
+    }
+}
+
+fn error(path: &str, e: impl std::fmt::Display) -> GameError {
+    GameError::ConfigError(format!("could not write {}: {}", path, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread::sleep;
+
+    fn micros(lines: &[String], stack: &str) -> u128 {
+        let line = lines.iter().find(|line| line.rsplit_once(' ').unwrap().0 == stack)
+            .unwrap_or_else(|| panic!("no line for {} in {:?}", stack, lines));
+        line.rsplit_once(' ').unwrap().1.parse().unwrap()
+    }
+
+    #[test]
+    fn nested_spans_are_collapsed() {
+        let profiler = Profiler::default();
+        profiler.set_enabled(true);
+        let start = Instant::now();
+        {
+            let _parent = profiler.span("parent");
+            sleep(Duration::from_millis(2));
+            let _child = profiler.span("child");
+            sleep(Duration::from_millis(3));
+        }
+        let elapsed = start.elapsed().as_micros();
+        profiler.end_frame();
+
+        let lines = profiler.collapsed();
+        assert_eq!(lines.len(), 2);
+        // The parent only counts the time outside of the child.
+        let (parent, child) = (micros(&lines, "parent"), micros(&lines, "parent;child"));
+        assert!(parent >= 2000 && child >= 3000);
+        assert!(parent + child <= elapsed);
+
+        let breakdown = profiler.breakdown();
+        assert_eq!(breakdown.iter().map(|(depth, name, _)| (*depth, name.as_str())).collect::<Vec<_>>(),
+            vec![(0, "parent"), (1, "child")]);
+    }
+
+    #[test]
+    fn disabled_profilers_record_nothing() {
+        let profiler = Profiler::default();
+        drop(profiler.span("ignored"));
+        assert!(profiler.collapsed().is_empty());
+    }
+}
@@ -0,0 +1,173 @@
+use ggez::nalgebra as na;
+use ggez::{GameError, GameResult};
+use gif::SetParameter;
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::BufWriter;
+
+use crate::world::World;
+
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FrameFormat {
+    /// One `frame_<tick>.png` per frame in the `path` directory.
+    Png,
+    /// One animated GIF at `path`, playing at the speed of the simulation.
+    Gif,
+}
+
+/// Where and how often to write frames. Nothing is rendered without a `path`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RenderConfig {
+    pub path: Option<String>,
+    pub format: FrameFormat,
+    /// Ticks between two frames.
+    pub every: u64,
+    /// Size of the frames relative to the world.
+    pub scale: f32,
+}
+
+impl Default for RenderConfig {
+    fn default() -> Self {
+        RenderConfig {
+            path: None,
+            format: FrameFormat::Png,
+            every: 30,
+            scale: 1.0,
+        }
+    }
+}
+
+/// Draws the world into an RGBA image without a graphics context, like the
+/// window does: terrain, pheromones, food, nests, danger zones, predators,
+/// and the ants on top.
+pub fn render(world: &World) -> RgbaImage {
+    let (width, height) = (world.terrain.evaporation[0].len() as u32, world.terrain.evaporation.len() as u32);
+    let mut image = RgbaImage::from_raw(width, height, world.terrain.to_rgba()).unwrap();
+
+    for layer in world.colonies.iter().flat_map(|colony| colony.pheromones.layers.iter()) {
+        let color = [layer.color.r, layer.color.g, layer.color.b];
+        for (y, row) in layer.grid.iter().enumerate() {
+            for (x, value) in row.iter().enumerate() {
+                if *value > 0.0 {
+                    blend(&mut image, x as i32, y as i32, color, value.min(1.0));
+                }
+            }
+        }
+    }
+
+    for position in world.food.position.iter().flatten() {
+        blend(&mut image, position.x as i32, position.y as i32, [1.0, 0.0, 0.0], 1.0);
+    }
+    for colony in world.colonies.iter().filter(|colony| colony.active) {
+        circle(&mut image, colony.home.position, colony.home.radius, [87.0 / 255.0, 67.0 / 255.0, 227.0 / 255.0]);
+    }
+    for zone in world.config.danger_zones.iter() {
+        circle(&mut image, na::Point2::new(zone.x, zone.y), zone.radius, [1.0, 0.0, 0.0]);
+    }
+    for position in world.predators.position.iter() {
+        disk(&mut image, *position, 5.0, [0.8, 0.2, 0.2]);
+    }
+    for position in world.ants.position.iter() {
+        for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)].iter() {
+            blend(&mut image, position.x as i32 + dx, position.y as i32 + dy, [1.0, 1.0, 1.0], 1.0);
+        }
+    }
+    image
+}
+
+// Pixels outside of the image are skipped.
+fn blend(image: &mut RgbaImage, x: i32, y: i32, color: [f32; 3], alpha: f32) {
+    if x < 0 || y < 0 || x as u32 >= image.width() || y as u32 >= image.height() {
+        return;
+    }
+    let pixel = image.get_pixel_mut(x as u32, y as u32);
+    for (channel, c) in pixel.0.iter_mut().zip(color.iter()) {
+        *channel = (*channel as f32 * (1.0 - alpha) + c * 255.0 * alpha) as u8;
+    }
+}
+
+fn circle(image: &mut RgbaImage, center: na::Point2<f32>, radius: f32, color: [f32; 3]) {
+    // Enough steps to leave no gaps along the outline.
+    let steps = (radius * std::f32::consts::TAU).ceil().max(8.0) as usize;
+    for k in 0..steps {
+        let angle = k as f32 / steps as f32 * std::f32::consts::TAU;
+        blend(image, (center.x + radius * angle.cos()) as i32, (center.y + radius * angle.sin()) as i32, color, 1.0);
+    }
+}
+
+fn disk(image: &mut RgbaImage, center: na::Point2<f32>, radius: f32, color: [f32; 3]) {
+    let r = radius.ceil() as i32;
+    for dy in -r..=r {
+        for dx in -r..=r {
+            if (dx * dx + dy * dy) as f32 <= radius * radius {
+                blend(image, center.x as i32 + dx, center.y as i32 + dy, color, 1.0);
+            }
+        }
+    }
+}
+
+/// Writes a frame of the world every `every` ticks.
+pub struct FrameWriter {
+    config: RenderConfig,
+    path: String,
+    // Frame delay in hundredths of a second, and the open GIF if writing one.
+    delay: u16,
+    gif: Option<gif::Encoder<BufWriter<File>>>,
+}
+
+impl FrameWriter {
+    /// `dt` is the time step, so GIFs play at the speed of the simulation.
+    pub fn create(path: &str, config: &RenderConfig, dt: f32) -> GameResult<Self> {
+        if let FrameFormat::Png = config.format {
+            fs::create_dir_all(path).map_err(|e| error(path, e))?;
+        }
+        Ok(FrameWriter {
+            config: config.clone(),
+            path: path.to_string(),
+            delay: (config.every as f32 * dt * 100.0).round().clamp(1.0, u16::MAX as f32) as u16,
+            gif: None,
+        })
+    }
+
+    pub fn record(&mut self, world: &World) -> GameResult {
+        if !world.tick.is_multiple_of(self.config.every.max(1)) {
+            return Ok(());
+        }
+
+        let mut image = render(world);
+        if self.config.scale != 1.0 {
+            let width = ((image.width() as f32 * self.config.scale) as u32).max(1);
+            let height = ((image.height() as f32 * self.config.scale) as u32).max(1);
+            image = image::imageops::resize(&image, width, height, image::FilterType::Triangle);
+        }
+
+        match self.config.format {
+            FrameFormat::Png => {
+                let path = format!("{}/frame_{:06}.png", self.path, world.tick);
+                image.save(&path).map_err(|e| error(&path, e))
+            },
+            FrameFormat::Gif => {
+                let (width, height) = (image.width() as u16, image.height() as u16);
+                if self.gif.is_none() {
+                    let file = File::create(&self.path).map_err(|e| error(&self.path, e))?;
+                    let mut encoder = gif::Encoder::new(BufWriter::new(file), width, height, &[])
+                        .map_err(|e| error(&self.path, e))?;
+                    encoder.set(gif::Repeat::Infinite).map_err(|e| error(&self.path, e))?;
+                    self.gif = Some(encoder);
+                }
+                let mut pixels = image.into_raw();
+                let mut frame = gif::Frame::from_rgba_speed(width, height, &mut pixels, 10);
+                frame.delay = self.delay;
+                let path = &self.path;
+                self.gif.as_mut().unwrap().write_frame(&frame).map_err(|e| error(path, e))
+            },
+        }
+    }
+}
+
+fn error(path: &str, e: impl std::fmt::Display) -> GameError {
+    GameError::ConfigError(format!("could not write {}: {}", path, e))
+}
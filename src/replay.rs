@@ -0,0 +1,83 @@
+use ggez::{GameError, GameResult};
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+use crate::config::Config;
+use crate::scenario::Event;
+use crate::world::World;
+
+/// A change made to a running world from outside of it: by a script or by
+/// clicking in the window. Scenario events are part of the config and are
+/// not interventions.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Intervention {
+    Event(Event),
+    SetParam { path: String, value: f64 },
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TimedIntervention {
+    /// The intervention took effect before this tick was stepped.
+    pub tick: u64,
+    #[serde(flatten)]
+    pub intervention: Intervention,
+}
+
+/// Everything needed to run a session again exactly: the config it started
+/// with (including its seed and time step) and the interventions made.
+/// Scripts are not run again, their effects are among the interventions.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Replay {
+    /// Ticks recorded.
+    pub ticks: u64,
+    pub config: Config,
+    pub interventions: Vec<TimedIntervention>,
+    // Index of the next intervention to apply while replaying.
+    #[serde(skip)]
+    next: usize,
+}
+
+impl Replay {
+    /// Starts recording a freshly created world stepped by `dt`.
+    pub fn start(world: &World, dt: f32) -> Self {
+        Replay {
+            ticks: 0,
+            config: Config { seed: Some(world.seed), dt: Some(dt), script: None, ..world.config.clone() },
+            interventions: Vec::new(),
+            next: 0,
+        }
+    }
+
+    /// Writes the session up to the world's current tick.
+    pub fn save(&mut self, world: &World, path: &str) -> GameResult {
+        self.ticks = world.tick;
+        self.interventions = world.interventions.clone();
+        let contents = toml::Value::try_from(&*self).and_then(|value| toml::to_string(&value))
+            .map_err(|e| GameError::ConfigError(format!("could not serialize replay: {}", e)))?;
+        fs::write(path, contents).map_err(|e| GameError::ConfigError(format!("could not write {}: {}", path, e)))
+    }
+
+    pub fn load(path: &str) -> GameResult<Self> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| GameError::ConfigError(format!("could not read {}: {}", path, e)))?;
+        toml::from_str(&contents).map_err(|e| GameError::ConfigError(format!("could not parse {}: {}", path, e)))
+    }
+
+    /// A world in the state the recorded one started in.
+    pub fn world(&self) -> GameResult<World> {
+        let mut world = World::new(self.config.clone())?;
+        world.initilize_positions();
+        Ok(world)
+    }
+
+    /// Makes the interventions recorded for the world's current tick.
+    pub fn apply(&mut self, world: &mut World) {
+        while let Some(timed) = self.interventions.get(self.next).filter(|timed| timed.tick <= world.tick) {
+            if let Err(e) = world.intervene(timed.intervention.clone()) {
+                println!("Replay error at tick {}: {}", timed.tick, e);
+            }
+            self.next += 1;
+        }
+    }
+}
@@ -0,0 +1,177 @@
+use ggez::nalgebra as na;
+use serde::{Deserialize, Serialize};
+
+use crate::config::ColonyConfig;
+
+/// Something that happens to the world at a given tick.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    /// Scatters `amount` pellets over a rectangle. With a `path` of offsets
+    /// from the rectangle the whole source walks along it at `speed` pixels
+    /// per second, looping back to its start.
+    AddFood {
+        name: String,
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+        amount: u32,
+        #[serde(default)]
+        path: Vec<[f32; 2]>,
+        #[serde(default)]
+        speed: f32,
+    },
+    /// Takes the pellets of a source out of the world. The starting strip is
+    /// called `initial`.
+    RemoveFood { name: String },
+    AddWall { x: f32, y: f32, width: f32, height: f32 },
+    /// Turns the rectangle back into the background terrain.
+    RemoveWall { x: f32, y: f32, width: f32, height: f32 },
+    AddNest(ColonyConfig),
+    /// Abandons a nest: its ants die and it stops hatching.
+    RemoveNest { colony: usize },
+    /// Washes away `strength` of every pheromone, 1 wipes them completely.
+    Rain { strength: f32 },
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ScheduledEvent {
+    pub tick: u64,
+    #[serde(flatten)]
+    pub event: Event,
+}
+
+/// A named group of pellets, optionally moving along a closed path.
+pub struct FoodSource {
+    pub name: String,
+    pub pellets: Vec<u32>,
+    path: Vec<na::Point2<f32>>,
+    speed: f32,
+    travelled: f32,
+}
+
+/// The scheduled events, in tick order, and the food sources they created.
+pub struct Scenario {
+    events: Vec<ScheduledEvent>,
+    next: usize,
+    pub sources: Vec<FoodSource>,
+}
+
+impl FoodSource {
+    pub fn new(name: &str, pellets: Vec<u32>, path: &[[f32; 2]], speed: f32) -> Self {
+        FoodSource {
+            name: name.to_string(),
+            pellets,
+            path: path.iter().map(|point| na::Point2::new(point[0], point[1])).collect(),
+            speed,
+            travelled: 0.0,
+        }
+    }
+
+    fn length(&self) -> f32 {
+        self.segments().map(|(a, b)| na::distance(&a, &b)).sum()
+    }
+
+    // The path is closed: the last point leads back to the first.
+    fn segments(&self) -> impl Iterator<Item = (na::Point2<f32>, na::Point2<f32>)> + '_ {
+        self.path.iter().zip(self.path.iter().cycle().skip(1)).map(|(a, b)| (*a, *b))
+    }
+
+    fn point_at(&self, mut distance: f32) -> na::Point2<f32> {
+        for (a, b) in self.segments() {
+            let length = na::distance(&a, &b);
+            if distance <= length && length > 0.0 {
+                return a + (b - a) * (distance / length);
+            }
+            distance -= length;
+        }
+        self.path[0]
+    }
+
+    /// Advances the source along its path and returns how far it moved.
+    pub fn advance(&mut self, dt: f32) -> Option<na::Vector2<f32>> {
+        let length = self.length();
+        if self.path.len() < 2 || self.speed <= 0.0 || length <= 0.0 {
+            return None;
+        }
+
+        let from = self.point_at(self.travelled);
+        self.travelled = (self.travelled + self.speed * dt) % length;
+        Some(self.point_at(self.travelled) - from)
+    }
+}
+
+impl Scenario {
+    pub fn new(events: &[ScheduledEvent]) -> Self {
+        let mut events = events.to_vec();
+        events.sort_by_key(|event| event.tick);
+        Scenario {
+            events,
+            next: 0,
+            sources: Vec::new(),
+        }
+    }
+
+    /// Events scheduled up to and including `tick` that have not fired yet.
+    pub fn due(&mut self, tick: u64) -> Vec<Event> {
+        let start = self.next;
+        while self.next < self.events.len() && self.events[self.next].tick <= tick {
+            self.next += 1;
+        }
+        self.events[start..self.next].iter().map(|event| event.event.clone()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: na::Vector2<f32>, b: na::Vector2<f32>) -> bool {
+        (a - b).norm() < 1e-4
+    }
+
+    #[test]
+    fn advance_follows_the_path() {
+        // A 10 by 10 square walked at 4 per second.
+        let mut source = FoodSource::new("square", Vec::new(), &[[0.0, 0.0], [10.0, 0.0], [10.0, 10.0], [0.0, 10.0]],
+            4.0);
+        assert!(close(source.advance(1.0).unwrap(), na::Vector2::new(4.0, 0.0)));
+        assert!(close(source.advance(1.0).unwrap(), na::Vector2::new(4.0, 0.0)));
+        // Turns the corner at (10, 0).
+        assert!(close(source.advance(1.0).unwrap(), na::Vector2::new(2.0, 2.0)));
+    }
+
+    #[test]
+    fn advance_loops_back_to_the_start() {
+        let mut source = FoodSource::new("line", Vec::new(), &[[0.0, 0.0], [10.0, 0.0]], 5.0);
+        let mut position = na::Point2::new(0.0, 0.0);
+        for _ in 0..4 {
+            position += source.advance(1.0).unwrap();
+        }
+        // Out along the line and back again: 20 in total.
+        assert!(close(position.coords, na::Vector2::new(0.0, 0.0)));
+        position += source.advance(1.0).unwrap();
+        assert!(close(position.coords, na::Vector2::new(5.0, 0.0)));
+    }
+
+    #[test]
+    fn still_sources_do_not_move() {
+        assert!(FoodSource::new("point", Vec::new(), &[[5.0, 5.0]], 4.0).advance(1.0).is_none());
+        assert!(FoodSource::new("slow", Vec::new(), &[[0.0, 0.0], [10.0, 0.0]], 0.0).advance(1.0).is_none());
+        assert!(FoodSource::new("none", Vec::new(), &[], 4.0).advance(1.0).is_none());
+    }
+
+    #[test]
+    fn due_returns_each_event_once() {
+        let events = [
+            ScheduledEvent { tick: 0, event: Event::Rain { strength: 0.5 } },
+            ScheduledEvent { tick: 10, event: Event::RemoveNest { colony: 0 } },
+        ];
+        let mut scenario = Scenario::new(&events);
+        assert_eq!(scenario.due(0).len(), 1);
+        assert_eq!(scenario.due(5).len(), 0);
+        assert_eq!(scenario.due(12).len(), 1);
+        assert_eq!(scenario.due(20).len(), 0);
+    }
+}
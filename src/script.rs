@@ -0,0 +1,224 @@
+use ggez::{GameError, GameResult};
+use rhai::{Engine, FnPtr, AST};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::config::Config;
+use crate::replay::Intervention;
+use crate::scenario::Event;
+use crate::world::World;
+
+#[derive(Clone, Default)]
+struct ColonySnapshot {
+    food_store: f64,
+    food_delivered: i64,
+    population: i64,
+    active: bool,
+}
+
+/// The part of the world scripts can read, refreshed before they run.
+#[derive(Default)]
+struct Snapshot {
+    tick: i64,
+    ants: i64,
+    food_left: i64,
+    predator_kills: i64,
+    colonies: Vec<ColonySnapshot>,
+    config: Config,
+}
+
+// A script function run at `first` and then every `period` ticks, if set.
+struct Callback {
+    first: u64,
+    period: Option<u64>,
+    function: FnPtr,
+}
+
+/// A Rhai script driving an experiment. The top level runs once at start
+/// and registers callbacks with `every(ticks, f)` or `at(tick, f)`; each
+/// callback gets the current tick. Coordinates and parameters are floats.
+///
+/// Reading: `tick()`, `ant_count()`, `food_left()`, `predator_kills()`,
+/// `colony_count()`, `population(c)`, `food_store(c)`, `food_delivered(c)`,
+/// `colony_active(c)`, `get_param(path)`.
+///
+/// Changing: `add_food(name, x, y, width, height, amount)`,
+/// `move_food(name, x, y, width, height, amount, path, speed)`,
+/// `remove_food(name)`, `add_wall(x, y, width, height)`,
+/// `remove_wall(x, y, width, height)`, `remove_nest(c)`, `rain(strength)`,
+/// `set_param(path, value)`. Changes are applied once the script returns, so
+/// reads within the same call still see the old world.
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+    snapshot: Rc<RefCell<Snapshot>>,
+    // Queued while the script runs and applied once it returns.
+    commands: Rc<RefCell<Vec<Intervention>>>,
+    callbacks: Rc<RefCell<Vec<Callback>>>,
+}
+
+impl Script {
+    pub fn load(path: &str) -> GameResult<Self> {
+        let mut engine = Engine::new();
+        // Debug builds default to very shallow limits.
+        engine.set_max_expr_depths(128, 64);
+        let snapshot = Rc::new(RefCell::new(Snapshot::default()));
+        let commands = Rc::new(RefCell::new(Vec::new()));
+        let callbacks = Rc::new(RefCell::new(Vec::new()));
+
+        register_queries(&mut engine, &snapshot);
+        register_commands(&mut engine, &commands);
+
+        let registered = callbacks.clone();
+        engine.register_fn("every", move |period: i64, function: FnPtr| {
+            registered.borrow_mut().push(Callback { first: 0, period: Some(period.max(1) as u64), function });
+        });
+        let registered = callbacks.clone();
+        engine.register_fn("at", move |tick: i64, function: FnPtr| {
+            registered.borrow_mut().push(Callback { first: tick.max(0) as u64, period: None, function });
+        });
+
+        let ast = engine.compile_file(path.into())
+            .map_err(|e| GameError::ConfigError(format!("could not load script {}: {}", path, e)))?;
+
+        Ok(Script { engine, ast, snapshot, commands, callbacks })
+    }
+
+    /// Runs the top level of the script.
+    pub fn start(&mut self, world: &mut World) {
+        self.refresh(world);
+        if let Err(e) = self.engine.run_ast(&self.ast) {
+            println!("Script error: {}", e);
+        }
+        self.apply(world);
+    }
+
+    /// Runs the callbacks due at the world's current tick.
+    pub fn update(&mut self, world: &mut World) {
+        let tick = world.tick;
+        let due: Vec<FnPtr> = self.callbacks.borrow().iter()
+            .filter(|callback| match callback.period {
+                Some(period) => tick >= callback.first && (tick - callback.first).is_multiple_of(period),
+                None => tick == callback.first,
+            })
+            .map(|callback| callback.function.clone())
+            .collect();
+        if due.is_empty() {
+            return;
+        }
+
+        self.refresh(world);
+        for function in due {
+            if let Err(e) = function.call::<rhai::Dynamic>(&self.engine, &self.ast, (tick as i64,)) {
+                println!("Script error at tick {}: {}", tick, e);
+            }
+        }
+        self.apply(world);
+    }
+
+    fn refresh(&self, world: &World) {
+        let mut snapshot = self.snapshot.borrow_mut();
+        snapshot.tick = world.tick as i64;
+        snapshot.ants = world.ants.len() as i64;
+        snapshot.food_left = world.food.position.iter().filter(|position| position.is_some()).count() as i64;
+        snapshot.predator_kills = world.predators.kills as i64;
+        snapshot.colonies = world.colonies.iter().map(|colony| ColonySnapshot {
+            food_store: colony.food_store as f64,
+            food_delivered: colony.food_delivered as i64,
+            population: 0,
+            active: colony.active,
+        }).collect();
+        for &c in world.ants.colony.iter() {
+            snapshot.colonies[c].population += 1;
+        }
+        snapshot.config = world.config.clone();
+    }
+
+    fn apply(&self, world: &mut World) {
+        for intervention in self.commands.borrow_mut().drain(..) {
+            if let Err(e) = world.intervene(intervention) {
+                println!("Script error: {}", e);
+            }
+        }
+    }
+}
+
+fn register_queries(engine: &mut Engine, snapshot: &Rc<RefCell<Snapshot>>) {
+    let s = snapshot.clone();
+    engine.register_fn("tick", move || s.borrow().tick);
+    let s = snapshot.clone();
+    engine.register_fn("ant_count", move || s.borrow().ants);
+    let s = snapshot.clone();
+    engine.register_fn("food_left", move || s.borrow().food_left);
+    let s = snapshot.clone();
+    engine.register_fn("predator_kills", move || s.borrow().predator_kills);
+    let s = snapshot.clone();
+    engine.register_fn("colony_count", move || s.borrow().colonies.len() as i64);
+
+    // Unknown colonies read as empty.
+    let colony = |snapshot: &Rc<RefCell<Snapshot>>, c: i64| -> ColonySnapshot {
+        snapshot.borrow().colonies.get(c as usize).cloned().unwrap_or_default()
+    };
+    let s = snapshot.clone();
+    engine.register_fn("population", move |c: i64| colony(&s, c).population);
+    let s = snapshot.clone();
+    engine.register_fn("food_store", move |c: i64| colony(&s, c).food_store);
+    let s = snapshot.clone();
+    engine.register_fn("food_delivered", move |c: i64| colony(&s, c).food_delivered);
+    let s = snapshot.clone();
+    engine.register_fn("colony_active", move |c: i64| colony(&s, c).active);
+
+    let s = snapshot.clone();
+    engine.register_fn("get_param", move |path: &str| s.borrow().config.get_param(path).unwrap_or(f64::NAN));
+}
+
+fn register_commands(engine: &mut Engine, commands: &Rc<RefCell<Vec<Intervention>>>) {
+    let q = commands.clone();
+    engine.register_fn("add_food", move |name: &str, x: f64, y: f64, width: f64, height: f64, amount: i64| {
+        q.borrow_mut().push(Intervention::Event(Event::AddFood {
+            name: name.to_string(), x: x as f32, y: y as f32, width: width as f32, height: height as f32,
+            amount: amount.max(0) as u32, path: Vec::new(), speed: 0.0,
+        }));
+    });
+    let q = commands.clone();
+    engine.register_fn("move_food", move |name: &str, x: f64, y: f64, width: f64, height: f64, amount: i64,
+        path: rhai::Array, speed: f64| {
+        // The path is an array of `[x, y]` offsets.
+        let path = path.into_iter()
+            .filter_map(|point| point.try_cast::<rhai::Array>())
+            .filter_map(|point| Some([point.first()?.as_float().ok()? as f32, point.get(1)?.as_float().ok()? as f32]))
+            .collect();
+        q.borrow_mut().push(Intervention::Event(Event::AddFood {
+            name: name.to_string(), x: x as f32, y: y as f32, width: width as f32, height: height as f32,
+            amount: amount.max(0) as u32, path, speed: speed as f32,
+        }));
+    });
+    let q = commands.clone();
+    engine.register_fn("remove_food", move |name: &str| {
+        q.borrow_mut().push(Intervention::Event(Event::RemoveFood { name: name.to_string() }));
+    });
+    let q = commands.clone();
+    engine.register_fn("add_wall", move |x: f64, y: f64, width: f64, height: f64| {
+        q.borrow_mut().push(Intervention::Event(Event::AddWall {
+            x: x as f32, y: y as f32, width: width as f32, height: height as f32,
+        }));
+    });
+    let q = commands.clone();
+    engine.register_fn("remove_wall", move |x: f64, y: f64, width: f64, height: f64| {
+        q.borrow_mut().push(Intervention::Event(Event::RemoveWall {
+            x: x as f32, y: y as f32, width: width as f32, height: height as f32,
+        }));
+    });
+    let q = commands.clone();
+    engine.register_fn("remove_nest", move |colony: i64| {
+        q.borrow_mut().push(Intervention::Event(Event::RemoveNest { colony: colony.max(0) as usize }));
+    });
+    let q = commands.clone();
+    engine.register_fn("rain", move |strength: f64| {
+        q.borrow_mut().push(Intervention::Event(Event::Rain { strength: strength as f32 }));
+    });
+    let q = commands.clone();
+    engine.register_fn("set_param", move |path: &str, value: f64| {
+        q.borrow_mut().push(Intervention::SetParam { path: path.to_string(), value });
+    });
+}
@@ -0,0 +1,265 @@
+use ggez::nalgebra as na;
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+
+use crate::pheromone::{PheromoneField, PheromoneLayer};
+use crate::utils;
+
+/// Heading (as an angle) and strength of the pheromone sensed on one layer.
+pub type Reading = Option<(f32, f32)>;
+
+/// A way for an ant to smell the pheromone field around it. Implementations
+/// return one reading per layer of the field, `None` when nothing was sensed.
+pub trait Sensor: Send + Sync {
+    fn sense(&self, position: na::Point2<f32>, angle: f32, pheromones: &PheromoneField) -> Vec<Reading>;
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SensorConfig {
+    Boxes { separation: f32, size: f32, angle: f32 },
+    Antennae { count: usize, spread: f32, length: f32 },
+    Cone { angle: f32, range: f32, rays: usize, steps: usize },
+    Gradient { step: f32 },
+}
+
+impl Default for SensorConfig {
+    fn default() -> Self {
+        let sensor = BoxSensor::default();
+        SensorConfig::Boxes { separation: sensor.separation, size: sensor.size, angle: sensor.angle }
+    }
+}
+
+pub fn from_config(config: &SensorConfig) -> Box<dyn Sensor> {
+    match *config {
+        SensorConfig::Boxes { separation, size, angle } => Box::new(BoxSensor { separation, size, angle }),
+        SensorConfig::Antennae { count, spread, length } => Box::new(AntennaeSensor { count, spread, length }),
+        SensorConfig::Cone { angle, range, rays, steps } => Box::new(ConeSensor { angle, range, rays, steps }),
+        SensorConfig::Gradient { step } => Box::new(GradientSensor { step }),
+    }
+}
+
+/// Three square vision boxes ahead of the ant, straight on and at `±angle`.
+pub struct BoxSensor {
+    pub separation: f32,
+    pub size: f32,
+    pub angle: f32,
+}
+
+impl Default for BoxSensor {
+    fn default() -> Self {
+        BoxSensor { separation: 10.0, size: 20.0, angle: PI / 5.0 }
+    }
+}
+
+impl BoxSensor {
+    fn angles(&self, angle: f32) -> [f32; 3] {
+        [angle, angle - self.angle, angle + self.angle]
+    }
+
+    fn boxes(&self, position: na::Point2<f32>, angles: &[f32]) -> Vec<(f32, f32, f32, f32)> {
+        let mut boxes = Vec::new();
+
+        for angle in angles.iter() {
+            let x_0 = position.x + (self.separation + self.size) * angle.cos() - self.size / 2.0;
+            let y_0 = position.y + (self.separation + self.size) * angle.sin();
+            let x_1 = x_0 + self.size;
+            let y_1 = y_0 + self.size;
+
+            boxes.push((x_0, y_0, x_1, y_1));
+        }
+        boxes
+    }
+
+    /// Pheromone in each box on one layer: straight on, at `-angle` and at
+    /// `+angle`.
+    pub fn scores(&self, position: na::Point2<f32>, angle: f32, layer: &PheromoneLayer) -> [f32; 3] {
+        let boxes = self.boxes(position, &self.angles(angle));
+        let mut scores = [0.0; 3];
+        for (score, (x_0, y_0, x_1, y_1)) in scores.iter_mut().zip(boxes) {
+            *score = layer.box_sum(x_0, y_0, x_1, y_1);
+        }
+        scores
+    }
+}
+
+impl Sensor for BoxSensor {
+    fn sense(&self, position: na::Point2<f32>, angle: f32, pheromones: &PheromoneField) -> Vec<Reading> {
+        let angles = self.angles(angle);
+        let boxes = self.boxes(position, &angles);
+
+        pheromones.layers.iter().map(|layer| {
+            let (index, score) = utils::index_calculator(&boxes, layer);
+            if score > 0.0 {
+                Some((angles[index], score))
+            } else {
+                None
+            }
+        }).collect()
+    }
+}
+
+/// `count` antennae fanned out evenly over `spread` radians, each smelling a
+/// single point `length` pixels away. The strongest antenna wins.
+pub struct AntennaeSensor {
+    pub count: usize,
+    pub spread: f32,
+    pub length: f32,
+}
+
+impl Sensor for AntennaeSensor {
+    fn sense(&self, position: na::Point2<f32>, angle: f32, pheromones: &PheromoneField) -> Vec<Reading> {
+        let angles: Vec<f32> = (0..self.count).map(|i| {
+            if self.count > 1 {
+                angle - self.spread / 2.0 + self.spread * i as f32 / (self.count - 1) as f32
+            } else {
+                angle
+            }
+        }).collect();
+
+        pheromones.layers.iter().map(|layer| {
+            let mut best = None;
+            for antenna in angles.iter() {
+                let score = layer.value(position.x + self.length * antenna.cos(),
+                    position.y + self.length * antenna.sin());
+                if score > 0.0 && best.is_none_or(|(_, h)| score > h) {
+                    best = Some((*antenna, score));
+                }
+            }
+            best
+        }).collect()
+    }
+}
+
+/// Samples a polar grid over a cone of half-angle `angle` and returns the
+/// concentration-weighted mean heading.
+pub struct ConeSensor {
+    pub angle: f32,
+    pub range: f32,
+    pub rays: usize,
+    pub steps: usize,
+}
+
+impl Sensor for ConeSensor {
+    fn sense(&self, position: na::Point2<f32>, angle: f32, pheromones: &PheromoneField) -> Vec<Reading> {
+        let rays = self.rays.max(1);
+        let steps = self.steps.max(1);
+
+        pheromones.layers.iter().map(|layer| {
+            let mut heading = na::Vector2::new(0.0, 0.0);
+            let mut total = 0.0;
+            for i in 0..rays {
+                let ray = if rays > 1 {
+                    angle - self.angle + 2.0 * self.angle * i as f32 / (rays - 1) as f32
+                } else {
+                    angle
+                };
+                let direction = na::Vector2::new(ray.cos(), ray.sin());
+                for step in 1..=steps {
+                    let point = position + direction * self.range * step as f32 / steps as f32;
+                    let value = layer.value(point.x, point.y);
+                    heading += direction * value;
+                    total += value;
+                }
+            }
+
+            if total > 0.0 {
+                Some((heading.y.atan2(heading.x), total))
+            } else {
+                None
+            }
+        }).collect()
+    }
+}
+
+/// Estimates the local concentration gradient with central differences and
+/// heads up the slope.
+pub struct GradientSensor {
+    pub step: f32,
+}
+
+impl GradientSensor {
+    fn gradient(&self, layer: &PheromoneLayer, position: na::Point2<f32>) -> na::Vector2<f32> {
+        let (x, y, h) = (position.x, position.y, self.step);
+        na::Vector2::new(
+            layer.value(x + h, y) - layer.value(x - h, y),
+            layer.value(x, y + h) - layer.value(x, y - h),
+        ) / (2.0 * h)
+    }
+}
+
+impl Sensor for GradientSensor {
+    fn sense(&self, position: na::Point2<f32>, _angle: f32, pheromones: &PheromoneField) -> Vec<Reading> {
+        pheromones.layers.iter().map(|layer| {
+            let gradient = self.gradient(layer, position);
+            let score = gradient.norm();
+            if score > 0.0 {
+                Some((gradient.y.atan2(gradient.x), score))
+            } else {
+                None
+            }
+        }).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::boundary::BoundaryMode;
+    use crate::config::PheromoneConfig;
+
+    // Ants sit at the origin heading right, with pheromone off to their
+    // right-hand side, at `+angle` (y grows downwards).
+    const ORIGIN: (f32, f32) = (100.0, 100.0);
+
+    fn field(x: f32, y: f32, radius: f32) -> PheromoneField {
+        let config = PheromoneConfig { name: "food".to_string(), decay: 0.0, diffusion: 0.0, color: [1.0, 1.0, 1.0] };
+        let mut field = PheromoneField::new(&[config], 200, 200, BoundaryMode::Reflect);
+        field.layers[0].deposit_disk(x, y, radius, 10.0);
+        field
+    }
+
+    fn heading(sensor: &dyn Sensor, field: &PheromoneField) -> f32 {
+        let readings = sensor.sense(na::Point2::new(ORIGIN.0, ORIGIN.1), 0.0, field);
+        assert_eq!(readings.len(), 1);
+        readings[0].expect("the deposit was not sensed").0
+    }
+
+    #[test]
+    fn boxes_point_to_the_deposit() {
+        let sensor = BoxSensor::default();
+        let ahead = field(124.0, 130.0, 3.0);
+        let scores = sensor.scores(na::Point2::new(ORIGIN.0, ORIGIN.1), 0.0, &ahead.layers[0]);
+        assert_eq!(scores[0], 0.0);
+        assert_eq!(scores[1], 0.0);
+        assert!(scores[2] > 0.0);
+        assert_eq!(heading(&sensor, &ahead), sensor.angle);
+
+        let behind = field(60.0, 100.0, 3.0);
+        assert!(sensor.sense(na::Point2::new(ORIGIN.0, ORIGIN.1), 0.0, &behind)[0].is_none());
+    }
+
+    #[test]
+    fn antennae_point_to_the_deposit() {
+        let sensor = AntennaeSensor { count: 3, spread: PI / 2.0, length: 20.0 };
+        let side = PI / 4.0;
+        let field = field(ORIGIN.0 + 20.0 * side.cos(), ORIGIN.1 + 20.0 * side.sin(), 3.0);
+        assert!((heading(&sensor, &field) - side).abs() < 1e-6);
+    }
+
+    #[test]
+    fn cones_point_to_the_deposit() {
+        let sensor = ConeSensor { angle: PI / 3.0, range: 30.0, rays: 5, steps: 5 };
+        let side = PI / 6.0;
+        let field = field(ORIGIN.0 + 20.0 * side.cos(), ORIGIN.1 + 20.0 * side.sin(), 4.0);
+        let heading = heading(&sensor, &field);
+        assert!(heading > 0.0 && heading <= sensor.angle);
+    }
+
+    #[test]
+    fn gradients_point_to_the_deposit() {
+        let sensor = GradientSensor { step: 2.0 };
+        let field = field(ORIGIN.0, ORIGIN.1 + 6.0, 5.0);
+        assert!((heading(&sensor, &field) - PI / 2.0).abs() < 1e-6);
+    }
+}
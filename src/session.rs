@@ -0,0 +1,256 @@
+use ggez::{GameError, GameResult};
+
+use crate::config::Config;
+use crate::heatmap::Heatmaps;
+use crate::metrics::Metrics;
+use crate::render::FrameWriter;
+use crate::replay::{Intervention, Replay};
+use crate::script::Script;
+use crate::trajectory;
+use crate::world::World;
+
+/// A running world together with what drives it, a script or a replay, and
+/// what records it. Shared by the window and headless runs.
+pub struct Session {
+    pub world: World,
+    script: Option<Script>,
+    replay: Option<Replay>,
+    // The replay being recorded and where to save it.
+    recording: Option<(Replay, String)>,
+    metrics: Option<Metrics>,
+    trajectories: Option<trajectory::Recorder>,
+    frames: Option<FrameWriter>,
+    heatmaps: Option<Heatmaps>,
+}
+
+impl Session {
+    /// Starts a world from `config`, recording a replay to `record` if set.
+    pub fn new(config: Config, record: Option<String>) -> GameResult<Self> {
+        if let (Some(seed), Some(_)) = (config.seed, &record) {
+            if seed > i64::MAX as u64 {
+                return Err(GameError::ConfigError(format!("cannot record seed {}, replays store seeds up to {}", seed,
+                    i64::MAX)));
+            }
+        }
+        let script = config.script.as_ref().map(|path| Script::load(path)).transpose()?;
+        let mut world = World::new(config)?;
+        world.initilize_positions();
+        let dt = world.config.dt.unwrap_or(1.0 / 60.0);
+        let recording = record.map(|path| (Replay::start(&world, dt), path));
+
+        let mut session = Session::with_world(world, script, None, recording)?;
+        if let Some(script) = &mut session.script {
+            script.start(&mut session.world);
+        }
+        Ok(session)
+    }
+
+    /// Plays a recorded session back. Metrics and trajectories are recorded
+    /// as set in the replay's config.
+    pub fn replay(replay: Replay) -> GameResult<Self> {
+        Session::with_world(replay.world()?, None, Some(replay), None)
+    }
+
+    fn with_world(world: World, script: Option<Script>, replay: Option<Replay>,
+        recording: Option<(Replay, String)>) -> GameResult<Self> {
+        if world.config.profile.path.is_some() {
+            world.profiler.set_enabled(true);
+        }
+        let config = &world.config;
+        let metrics = config.metrics.path.as_ref().map(|path| Metrics::create(path, config)).transpose()?;
+        let trajectories = config.trajectories.path.as_ref()
+            .map(|path| trajectory::Recorder::create(path, &config.trajectories))
+            .transpose()?;
+        let frames = config.render.path.as_ref()
+            .map(|path| FrameWriter::create(path, &config.render, config.dt.unwrap_or(1.0 / 60.0)))
+            .transpose()?;
+        let heatmaps = config.heatmaps.path.as_ref().map(|path| Heatmaps::create(path, &config.heatmaps)).transpose()?;
+        Ok(Session { world, script, replay, recording, metrics, trajectories, frames, heatmaps })
+    }
+
+    pub fn replaying(&self) -> bool {
+        self.replay.is_some()
+    }
+
+    /// An edit made by hand. Ignored while replaying, the replay has its own.
+    pub fn intervene(&mut self, intervention: Intervention) -> GameResult {
+        if self.replaying() {
+            return Ok(());
+        }
+        self.world.intervene(intervention)
+    }
+
+    /// Runs the script or replay for this tick, records it and steps the world.
+    pub fn step(&mut self, dt: f32) -> GameResult {
+        if let Some(script) = &mut self.script {
+            script.update(&mut self.world);
+        }
+        if let Some(replay) = &mut self.replay {
+            replay.apply(&mut self.world);
+        }
+        let profiler = self.world.profiler.clone();
+        let output = profiler.span("output");
+        if let Some(metrics) = &mut self.metrics {
+            metrics.record(&self.world)?;
+        }
+        if let Some(trajectories) = &mut self.trajectories {
+            trajectories.record(&self.world)?;
+        }
+        if let Some(frames) = &mut self.frames {
+            frames.record(&self.world)?;
+        }
+        if let Some(heatmaps) = &self.heatmaps {
+            heatmaps.record(&self.world)?;
+        }
+        drop(output);
+        self.world.step(dt)
+    }
+
+    /// Saves the replay being recorded and the profile, if any, and closes
+    /// the animation. Metrics, trajectories, frames and heatmaps due at the
+    /// last tick are written here, as `step` records before stepping.
+    pub fn finish(&mut self) -> GameResult {
+        if let Some(frames) = &mut self.frames {
+            frames.record(&self.world)?;
+        }
+        self.frames = None;
+        if let Some(metrics) = &mut self.metrics {
+            metrics.record(&self.world)?;
+        }
+        if let Some(trajectories) = &mut self.trajectories {
+            trajectories.record(&self.world)?;
+        }
+        if let Some(heatmaps) = &self.heatmaps {
+            heatmaps.record(&self.world)?;
+        }
+        if let Some(path) = &self.world.config.profile.path {
+            self.world.profiler.save(path)?;
+            println!("saved the profile to {0}.folded and {0}.svg", path);
+        }
+        if let Some((replay, path)) = &mut self.recording {
+            replay.save(&self.world, path)?;
+            println!("saved the replay to {}", path);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scenario::Event;
+
+    #[test]
+    fn replays_match_the_recording() {
+        let path = std::env::temp_dir().join(format!("ant-simulation-replay-{}.toml", std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+        let dt = 1.0 / 60.0;
+        let mut config = Config { seed: Some(7), dt: Some(dt), ..Config::default() };
+        config.colonies.truncate(1);
+        config.colonies[0].ants = 50;
+
+        let mut recorded = Session::new(config, Some(path.clone())).unwrap();
+        for tick in 0..6 {
+            if tick == 2 {
+                let param = Intervention::SetParam { path: "recruitment_radius".to_string(), value: 50.0 };
+                recorded.intervene(param).unwrap();
+            }
+            if tick == 4 {
+                let wall = Event::AddWall { x: 300.0, y: 300.0, width: 200.0, height: 200.0 };
+                recorded.intervene(Intervention::Event(wall)).unwrap();
+            }
+            recorded.step(dt).unwrap();
+        }
+        recorded.finish().unwrap();
+
+        let replay = Replay::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        let replay = replay.unwrap();
+        assert_eq!(replay.ticks, 6);
+        assert_eq!(replay.interventions.len(), 2);
+
+        let mut replayed = Session::replay(replay).unwrap();
+        for _ in 0..6 {
+            replayed.step(dt).unwrap();
+        }
+        assert_eq!(replayed.world.config.recruitment_radius, 50.0);
+        assert_eq!(replayed.world.ants.id, recorded.world.ants.id);
+        assert_eq!(replayed.world.ants.position, recorded.world.ants.position);
+        assert_eq!(replayed.world.ants.state, recorded.world.ants.state);
+    }
+
+    #[test]
+    fn seeds_replays_cannot_store_are_rejected() {
+        let config = Config { seed: Some(u64::MAX), ..Config::default() };
+        assert!(Session::new(config, Some("unused.toml".to_string())).is_err());
+    }
+
+    #[test]
+    fn metrics_include_the_last_tick() {
+        let path = std::env::temp_dir().join(format!("ant-simulation-metrics-{}.csv", std::process::id()));
+        let mut config = Config { seed: Some(3), ..Config::default() };
+        config.colonies.truncate(1);
+        config.colonies[0].ants = 10;
+        config.metrics.path = Some(path.to_str().unwrap().to_string());
+        config.metrics.interval = 2;
+
+        let mut session = Session::new(config, None).unwrap();
+        for _ in 0..4 {
+            session.step(1.0 / 60.0).unwrap();
+        }
+        session.finish().unwrap();
+
+        let text = std::fs::read_to_string(&path);
+        std::fs::remove_file(&path).unwrap();
+        let ticks: Vec<String> = text.unwrap().lines().skip(1)
+            .map(|line| line.split(',').next().unwrap().to_string())
+            .collect();
+        assert_eq!(ticks, ["0", "2", "4"]);
+    }
+
+    #[test]
+    fn trajectories_include_the_last_tick() {
+        let path = std::env::temp_dir().join(format!("ant-simulation-trajectories-{}.csv", std::process::id()));
+        let mut config = Config { seed: Some(3), ..Config::default() };
+        config.colonies.truncate(1);
+        config.colonies[0].ants = 10;
+        config.trajectories.path = Some(path.to_str().unwrap().to_string());
+        config.trajectories.every = 2;
+
+        let mut session = Session::new(config, None).unwrap();
+        for _ in 0..4 {
+            session.step(1.0 / 60.0).unwrap();
+        }
+        session.finish().unwrap();
+
+        let text = std::fs::read_to_string(&path);
+        std::fs::remove_file(&path).unwrap();
+        let samples: Vec<u64> = text.unwrap().lines().skip(1)
+            .map(|line| line.split(',').collect::<Vec<_>>())
+            .filter(|fields| fields[2].is_empty())
+            .map(|fields| fields[0].parse().unwrap())
+            .collect();
+        assert_eq!(samples.iter().filter(|&&tick| tick == 4).count(), session.world.ants.len());
+    }
+
+    #[test]
+    fn frames_include_the_last_tick() {
+        let path = std::env::temp_dir().join(format!("ant-simulation-frames-{}", std::process::id()));
+        let mut config = Config { seed: Some(3), ..Config::default() };
+        config.colonies.truncate(1);
+        config.colonies[0].ants = 10;
+        config.render.path = Some(path.to_str().unwrap().to_string());
+        config.render.every = 2;
+        config.render.scale = 0.25;
+
+        let mut session = Session::new(config, None).unwrap();
+        for _ in 0..4 {
+            session.step(1.0 / 60.0).unwrap();
+        }
+        session.finish().unwrap();
+
+        let last = path.join("frame_000004.png").exists();
+        std::fs::remove_dir_all(&path).unwrap();
+        assert!(last);
+    }
+}
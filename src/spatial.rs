@@ -0,0 +1,163 @@
+use ggez::nalgebra as na;
+
+/// Uniform grid over the world that buckets indices by position, so radius
+/// queries only have to look at the few cells the circle overlaps. Queries
+/// do not wrap around the edges, even in `BoundaryMode::Wrap`.
+pub struct SpatialHash {
+    cell_size: f32,
+    columns: usize,
+    rows: usize,
+    cells: Vec<Vec<u32>>,
+}
+
+impl SpatialHash {
+    pub fn new(width: f32, height: f32, cell_size: f32) -> Self {
+        let columns = (width / cell_size).ceil().max(1.0) as usize;
+        let rows = (height / cell_size).ceil().max(1.0) as usize;
+
+        SpatialHash {
+            cell_size,
+            columns,
+            rows,
+            cells: vec![Vec::new(); columns * rows],
+        }
+    }
+
+    // Points outside of the world are clamped into the border cells.
+    fn cell(&self, position: &na::Point2<f32>) -> (usize, usize) {
+        let x = ((position.x / self.cell_size).max(0.0) as usize).min(self.columns - 1);
+        let y = ((position.y / self.cell_size).max(0.0) as usize).min(self.rows - 1);
+        (x, y)
+    }
+
+    pub fn clear(&mut self) {
+        for cell in self.cells.iter_mut() {
+            cell.clear();
+        }
+    }
+
+    pub fn insert(&mut self, index: u32, position: &na::Point2<f32>) {
+        let (x, y) = self.cell(position);
+        self.cells[y * self.columns + x].push(index);
+    }
+
+    pub fn remove(&mut self, index: u32, position: &na::Point2<f32>) {
+        let (x, y) = self.cell(position);
+        let cell = &mut self.cells[y * self.columns + x];
+        if let Some(i) = cell.iter().position(|&j| j == index) {
+            cell.swap_remove(i);
+        }
+    }
+
+    pub fn rebuild<'a>(&mut self, positions: impl Iterator<Item = &'a na::Point2<f32>>) {
+        self.clear();
+        for (i, position) in positions.enumerate() {
+            self.insert(i as u32, position);
+        }
+    }
+
+    /// Calls `visit` with every index stored in the cells overlapping the
+    /// circle. Callers still have to check the exact distance.
+    pub fn query(&self, position: &na::Point2<f32>, radius: f32, mut visit: impl FnMut(u32)) {
+        let (x_0, y_0) = self.cell(&na::Point2::new(position.x - radius, position.y - radius));
+        let (x_1, y_1) = self.cell(&na::Point2::new(position.x + radius, position.y + radius));
+
+        for y in y_0..=y_1 {
+            for x in x_0..=x_1 {
+                for &index in self.cells[y * self.columns + x].iter() {
+                    visit(index);
+                }
+            }
+        }
+    }
+
+    /// Closest stored index within `radius` whose position is given by `lookup`.
+    pub fn nearest(&self, position: &na::Point2<f32>, radius: f32,
+        lookup: impl Fn(u32) -> Option<na::Point2<f32>>) -> Option<u32> {
+        let mut best = None;
+        let mut best_distance = radius * radius;
+
+        self.query(position, radius, |index| {
+            if let Some(candidate) = lookup(index) {
+                let distance = na::distance_squared(&candidate, position);
+                // Ties go to the lowest index so the result does not depend on
+                // the order inside a cell.
+                if distance < best_distance || (distance == best_distance && best.is_some_and(|b| index < b)) {
+                    best_distance = distance;
+                    best = Some(index);
+                }
+            }
+        });
+
+        best
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash(points: &[na::Point2<f32>]) -> SpatialHash {
+        let mut hash = SpatialHash::new(100.0, 100.0, 10.0);
+        hash.rebuild(points.iter());
+        hash
+    }
+
+    #[test]
+    fn query_visits_neighbouring_cells() {
+        // On both sides of the cell boundary at x = 10, and two cells away.
+        let points = [na::Point2::new(9.5, 5.0), na::Point2::new(10.5, 5.0), na::Point2::new(25.0, 5.0)];
+        let hash = hash(&points);
+
+        let mut found = Vec::new();
+        hash.query(&na::Point2::new(9.9, 5.0), 1.0, |index| found.push(index));
+        found.sort_unstable();
+        assert_eq!(found, vec![0, 1]);
+    }
+
+    #[test]
+    fn query_clamps_to_the_world() {
+        let points = [na::Point2::new(0.0, 0.0), na::Point2::new(99.0, 99.0)];
+        let hash = hash(&points);
+
+        let mut found = Vec::new();
+        hash.query(&na::Point2::new(-50.0, -50.0), 5.0, |index| found.push(index));
+        assert_eq!(found, vec![0]);
+        found.clear();
+        hash.query(&na::Point2::new(150.0, 150.0), 5.0, |index| found.push(index));
+        assert_eq!(found, vec![1]);
+    }
+
+    #[test]
+    fn nearest_across_cells() {
+        let points = [na::Point2::new(19.0, 19.0), na::Point2::new(21.0, 20.5), na::Point2::new(40.0, 40.0)];
+        let hash = hash(&points);
+        let lookup = |index: u32| Some(points[index as usize]);
+
+        assert_eq!(hash.nearest(&na::Point2::new(20.8, 20.5), 5.0, lookup), Some(1));
+        assert_eq!(hash.nearest(&na::Point2::new(19.2, 19.0), 5.0, lookup), Some(0));
+        assert_eq!(hash.nearest(&na::Point2::new(30.0, 30.0), 5.0, lookup), None);
+    }
+
+    #[test]
+    fn nearest_prefers_the_lowest_index_on_ties() {
+        let points = [na::Point2::new(12.0, 5.0), na::Point2::new(8.0, 5.0)];
+        let mut hash = SpatialHash::new(100.0, 100.0, 10.0);
+        // Inserted in reverse so the cell order differs from the index order.
+        hash.insert(1, &points[1]);
+        hash.insert(0, &points[0]);
+        let lookup = |index: u32| Some(points[index as usize]);
+
+        assert_eq!(hash.nearest(&na::Point2::new(10.0, 5.0), 5.0, lookup), Some(0));
+    }
+
+    #[test]
+    fn removed_indices_are_not_found() {
+        let points = [na::Point2::new(5.0, 5.0), na::Point2::new(6.0, 5.0)];
+        let mut hash = hash(&points);
+        hash.remove(0, &points[0]);
+        let lookup = |index: u32| Some(points[index as usize]);
+
+        assert_eq!(hash.nearest(&na::Point2::new(5.0, 5.0), 5.0, lookup), Some(1));
+    }
+}
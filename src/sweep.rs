@@ -0,0 +1,181 @@
+use ggez::{GameError, GameResult};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+use crate::config::Config;
+use crate::world::World;
+
+/// A config parameter to sweep, by its dotted path as in `Config::get_param`.
+/// Grids go through `values`; random samples draw from `values` when given
+/// and from the `min`..`max` range otherwise.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SweepParam {
+    pub path: String,
+    #[serde(default)]
+    pub values: Vec<f64>,
+    #[serde(default)]
+    pub min: f64,
+    #[serde(default)]
+    pub max: f64,
+}
+
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SweepMode {
+    /// Every combination of the listed values.
+    Grid,
+    /// `samples` random combinations.
+    Random,
+}
+
+/// Settings of a parameter sweep, see `sweep`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SweepConfig {
+    pub params: Vec<SweepParam>,
+    pub mode: SweepMode,
+    pub samples: usize,
+    /// Every combination is run once per seed.
+    pub seeds: Vec<u64>,
+    pub ticks: u64,
+}
+
+impl Default for SweepConfig {
+    fn default() -> Self {
+        SweepConfig {
+            params: Vec::new(),
+            mode: SweepMode::Grid,
+            samples: 20,
+            seeds: vec![1],
+            ticks: 1800,
+        }
+    }
+}
+
+impl SweepConfig {
+    /// Rejects parameters with nothing to sweep through.
+    pub fn validate(&self) -> GameResult {
+        for param in self.params.iter() {
+            let error = |message: &str| Err(GameError::ConfigError(format!("cannot sweep {}: {}", param.path, message)));
+            match self.mode {
+                SweepMode::Grid if param.values.is_empty() => return error("a grid needs values"),
+                SweepMode::Random if param.values.is_empty()
+                    && (!param.min.is_finite() || !param.max.is_finite() || param.min > param.max) => {
+                    return error(&format!("{}..{} is not a range", param.min, param.max));
+                },
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How a run ended up.
+pub struct Summary {
+    pub delivered: u64,
+    pub ants: usize,
+    pub food_left: usize,
+    pub births: u64,
+    pub deaths: u64,
+    pub predator_kills: u64,
+}
+
+pub struct Run {
+    pub values: Vec<f64>,
+    pub seed: u64,
+    pub summary: Summary,
+}
+
+impl Summary {
+    pub fn new(world: &World) -> Self {
+        let stats = world.colonies.iter().flat_map(|colony| colony.caste_stats.iter());
+        Summary {
+            delivered: world.colonies.iter().map(|colony| colony.food_delivered).sum(),
+            ants: world.ants.len(),
+            food_left: world.food.position.iter().filter(|position| position.is_some()).count(),
+            births: stats.clone().map(|stats| stats.births).sum(),
+            deaths: stats.map(|stats| stats.deaths).sum(),
+            predator_kills: world.predators.kills,
+        }
+    }
+}
+
+/// The parameter values of every combination to run.
+pub fn combinations(settings: &SweepConfig, rng: &mut StdRng) -> Vec<Vec<f64>> {
+    match settings.mode {
+        SweepMode::Grid => settings.params.iter().fold(vec![Vec::new()], |combinations, param| {
+            combinations.iter()
+                .flat_map(|combination| param.values.iter().map(move |value| {
+                    let mut combination = combination.clone();
+                    combination.push(*value);
+                    combination
+                }))
+                .collect()
+        }),
+        SweepMode::Random => (0..settings.samples)
+            .map(|_| settings.params.iter()
+                .map(|param| match param.values.len() {
+                    0 => rng.gen_range(param.min..=param.max),
+                    n => param.values[rng.gen_range(0..n)],
+                })
+                .collect())
+            .collect(),
+    }
+}
+
+/// Runs every combination of the `[sweep]` parameters once per seed, in
+/// parallel headless worlds, and returns the runs in a fixed order.
+pub fn sweep(config: &Config) -> GameResult<Vec<Run>> {
+    let settings = &config.sweep;
+    if settings.params.is_empty() {
+        return Err(GameError::ConfigError("no parameters to sweep, add some to [sweep]".to_string()));
+    }
+    settings.validate()?;
+
+    let mut rng = StdRng::seed_from_u64(config.seed.unwrap_or(0));
+    let mut jobs = Vec::new();
+    for values in combinations(settings, &mut rng) {
+        let mut candidate = Config { script: None, ..config.clone() };
+        for (param, value) in settings.params.iter().zip(values.iter()) {
+            candidate.set_param(&param.path, *value)?;
+        }
+        for &seed in settings.seeds.iter() {
+            jobs.push((values.clone(), Config { seed: Some(seed), ..candidate.clone() }));
+        }
+    }
+
+    let dt = config.dt.unwrap_or(1.0 / 60.0);
+    jobs.into_par_iter()
+        .map(|(values, config)| {
+            let seed = config.seed.unwrap();
+            let mut world = World::new(config)?;
+            world.initilize_positions();
+            for _ in 0..settings.ticks {
+                world.step(dt)?;
+            }
+            Ok(Run { values, seed, summary: Summary::new(&world) })
+        })
+        .collect()
+}
+
+/// Writes one line per run: the seed, the parameter values and the summary.
+pub fn write_csv(path: &str, params: &[SweepParam], runs: &[Run], ticks: u64) -> GameResult {
+    let mut csv = String::from("seed");
+    for param in params.iter() {
+        csv += &format!(",{}", param.path);
+    }
+    csv += ",delivered,delivered_per_tick,ants,food_left,births,deaths,predator_kills\n";
+    for run in runs.iter() {
+        csv += &run.seed.to_string();
+        for value in run.values.iter() {
+            csv += &format!(",{}", value);
+        }
+        let summary = &run.summary;
+        csv += &format!(",{},{},{},{},{},{},{}\n", summary.delivered, summary.delivered as f64 / ticks.max(1) as f64,
+            summary.ants, summary.food_left, summary.births, summary.deaths, summary.predator_kills);
+    }
+    fs::write(path, csv).map_err(|e| GameError::ConfigError(format!("could not write {}: {}", path, e)))
+}
@@ -0,0 +1,225 @@
+use ggez::nalgebra as na;
+use ggez::{GameError, GameResult};
+use serde::{Deserialize, Serialize};
+
+/// A type of ground. `speed` scales how fast ants walk over it and
+/// `evaporation` how fast pheromones fade on it.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TerrainKind {
+    pub name: String,
+    pub speed: f32,
+    pub evaporation: f32,
+    #[serde(default = "passable")]
+    pub passable: bool,
+    pub color: [f32; 3],
+}
+
+/// A rectangle of the world covered by the named kind.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TerrainRegion {
+    pub kind: String,
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+/// The first kind covers the whole world. An `image` is stretched over the
+/// world and every pixel gets the kind with the closest colour; `regions`
+/// are painted on top in order.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TerrainConfig {
+    pub image: Option<String>,
+    pub kinds: Vec<TerrainKind>,
+    pub regions: Vec<TerrainRegion>,
+}
+
+pub struct Terrain {
+    pub kinds: Vec<TerrainKind>,
+    // Kind index of every pixel.
+    cells: Vec<Vec<u8>>,
+    /// Evaporation multiplier of every pixel, laid out like the pheromone grids.
+    pub evaporation: Vec<Vec<f32>>,
+    /// Bumped on every change, so the renderer knows to redraw the background.
+    pub version: u32,
+}
+
+fn passable() -> bool {
+    true
+}
+
+impl TerrainKind {
+    fn new(name: &str, speed: f32, evaporation: f32, passable: bool, color: [f32; 3]) -> Self {
+        TerrainKind {
+            name: name.to_string(),
+            speed,
+            evaporation,
+            passable,
+            color,
+        }
+    }
+
+    pub fn defaults() -> Vec<TerrainKind> {
+        vec![
+            TerrainKind::new("ground", 1.0, 1.0, true, [0.0, 0.0, 0.0]),
+            TerrainKind::new("sand", 0.6, 1.5, true, [0.3, 0.25, 0.1]),
+            TerrainKind::new("grass", 0.8, 0.6, true, [0.05, 0.2, 0.05]),
+            TerrainKind::new("water", 0.0, 1.0, false, [0.05, 0.1, 0.35]),
+            TerrainKind::new("wall", 0.0, 1.0, false, [0.4, 0.4, 0.4]),
+        ]
+    }
+}
+
+impl Default for TerrainConfig {
+    fn default() -> Self {
+        TerrainConfig {
+            image: None,
+            kinds: TerrainKind::defaults(),
+            regions: Vec::new(),
+        }
+    }
+}
+
+impl Terrain {
+    pub fn new(config: &TerrainConfig, width: usize, height: usize) -> GameResult<Self> {
+        let kinds = if config.kinds.is_empty() { TerrainKind::defaults() } else { config.kinds.clone() };
+        if kinds.len() > u8::MAX as usize {
+            return Err(GameError::ConfigError(format!("at most {} terrain kinds are supported", u8::MAX)));
+        }
+        let mut cells = vec![vec![0u8; width]; height];
+
+        if let Some(path) = &config.image {
+            let image = image::open(path)
+                .map_err(|e| GameError::ConfigError(format!("could not load terrain {}: {}", path, e)))?
+                .to_rgb();
+            let image = image::imageops::resize(&image, width as u32, height as u32, image::FilterType::Nearest);
+            for (x, y, pixel) in image.enumerate_pixels() {
+                cells[y as usize][x as usize] = closest_kind(&kinds, pixel.0);
+            }
+        }
+
+        let evaporation = cells.iter()
+            .map(|row| row.iter().map(|&cell| kinds[cell as usize].evaporation).collect())
+            .collect();
+
+        let mut terrain = Terrain { kinds, cells, evaporation, version: 0 };
+        for region in config.regions.iter() {
+            terrain.paint(region)?;
+        }
+        Ok(terrain)
+    }
+
+    /// Covers the region with its kind.
+    pub fn paint(&mut self, region: &TerrainRegion) -> GameResult {
+        let kind = self.kinds.iter().position(|kind| kind.name == region.kind)
+            .ok_or_else(|| GameError::ConfigError(format!("unknown terrain kind {}", region.kind)))?;
+        let (height, width) = (self.cells.len(), self.cells[0].len());
+        let x_0 = (region.x.max(0.0) as usize).min(width);
+        let y_0 = (region.y.max(0.0) as usize).min(height);
+        let x_1 = ((region.x + region.width).max(0.0) as usize).min(width);
+        let y_1 = ((region.y + region.height).max(0.0) as usize).min(height);
+        for y in y_0..y_1 {
+            for x in x_0..x_1 {
+                self.cells[y][x] = kind as u8;
+                self.evaporation[y][x] = self.kinds[kind].evaporation;
+            }
+        }
+        self.version += 1;
+        Ok(())
+    }
+
+    /// Name of the kind covering the world before anything is painted.
+    pub fn background(&self) -> &str {
+        &self.kinds[0].name
+    }
+
+    // Positions outside of the world take the kind of the nearest border cell.
+    fn kind(&self, position: &na::Point2<f32>) -> &TerrainKind {
+        let y = (position.y.max(0.0) as usize).min(self.cells.len() - 1);
+        let x = (position.x.max(0.0) as usize).min(self.cells[y].len() - 1);
+        &self.kinds[self.cells[y][x] as usize]
+    }
+
+    pub fn speed(&self, position: &na::Point2<f32>) -> f32 {
+        self.kind(position).speed
+    }
+
+    pub fn passable(&self, position: &na::Point2<f32>) -> bool {
+        self.kind(position).passable
+    }
+
+    /// Centre of the passable cell closest to `position` within `radius`
+    /// cells, searched in square rings around it.
+    pub fn nearest_passable(&self, position: &na::Point2<f32>, radius: usize) -> Option<na::Point2<f32>> {
+        let (height, width) = (self.cells.len() as i64, self.cells[0].len() as i64);
+        let (x, y) = (position.x as i64, position.y as i64);
+        for r in 0..=radius as i64 {
+            let mut best: Option<(f32, na::Point2<f32>)> = None;
+            for j in (y - r).max(0)..=(y + r).min(height - 1) {
+                for i in (x - r).max(0)..=(x + r).min(width - 1) {
+                    let on_ring = (i - x).abs() == r || (j - y).abs() == r;
+                    if !on_ring || !self.kinds[self.cells[j as usize][i as usize] as usize].passable {
+                        continue;
+                    }
+                    let cell = na::Point2::new(i as f32 + 0.5, j as f32 + 0.5);
+                    let distance = na::distance_squared(&cell, position);
+                    if best.is_none_or(|(closest, _)| distance < closest) {
+                        best = Some((distance, cell));
+                    }
+                }
+            }
+            if let Some((_, cell)) = best {
+                return Some(cell);
+            }
+        }
+        None
+    }
+
+    /// Colour of every pixel as RGBA bytes, for drawing the background.
+    pub fn to_rgba(&self) -> Vec<u8> {
+        let mut rgba = Vec::with_capacity(self.cells.len() * self.cells[0].len() * 4);
+        for &cell in self.cells.iter().flatten() {
+            let color = self.kinds[cell as usize].color;
+            rgba.extend(color.iter().map(|c| (c * 255.0) as u8));
+            rgba.push(255);
+        }
+        rgba
+    }
+}
+
+fn closest_kind(kinds: &[TerrainKind], pixel: [u8; 3]) -> u8 {
+    let distance = |kind: &TerrainKind| -> f32 {
+        kind.color.iter().zip(pixel.iter())
+            .map(|(c, p)| (c * 255.0 - *p as f32).powi(2))
+            .sum()
+    };
+    let mut best = 0;
+    for (i, kind) in kinds.iter().enumerate() {
+        if distance(kind) < distance(&kinds[best]) {
+            best = i;
+        }
+    }
+    best as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nearest_passable_leaves_the_wall() {
+        let mut terrain = Terrain::new(&TerrainConfig::default(), 50, 50).unwrap();
+        terrain.paint(&TerrainRegion { kind: "wall".to_string(), x: 10.0, y: 10.0, width: 10.0, height: 20.0 })
+            .unwrap();
+
+        // Closest to the left side of the wall.
+        let open = terrain.nearest_passable(&na::Point2::new(11.5, 20.5), 20).unwrap();
+        assert_eq!(open, na::Point2::new(9.5, 20.5));
+        assert!(terrain.passable(&open));
+        // Already open ground stays where it is.
+        assert_eq!(terrain.nearest_passable(&na::Point2::new(5.5, 5.5), 20), Some(na::Point2::new(5.5, 5.5)));
+        // Too deep inside for the radius.
+        assert_eq!(terrain.nearest_passable(&na::Point2::new(15.5, 20.5), 2), None);
+    }
+}
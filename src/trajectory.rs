@@ -0,0 +1,193 @@
+use ggez::{GameError, GameResult};
+use ggez::nalgebra as na;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use crate::world::World;
+
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrajectoryFormat {
+    /// `tick,ant,event,x,y,vx,vy,angle,state,carried` with a header line.
+    Csv,
+    /// The bytes `ANTTRAJ1`, then one 31 byte little-endian record per line
+    /// of the CSV: tick and ant as u32, the event as u8 (see `Event`), x, y,
+    /// vx, vy and angle as f32, state and carried as u8.
+    Binary,
+}
+
+/// Which ants to follow and where to write their paths. Nothing is recorded
+/// without a `path`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TrajectoryConfig {
+    pub path: Option<String>,
+    pub format: TrajectoryFormat,
+    /// Ticks between two samples of an ant. Events are written as they happen.
+    pub every: u64,
+    /// Ids of the ants to follow, all when empty.
+    pub ants: Vec<u64>,
+    /// Only follows ants of this colony.
+    pub colony: Option<usize>,
+}
+
+impl Default for TrajectoryConfig {
+    fn default() -> Self {
+        TrajectoryConfig {
+            path: None,
+            format: TrajectoryFormat::Csv,
+            every: 1,
+            ants: Vec::new(),
+            colony: None,
+        }
+    }
+}
+
+/// Why a line was written.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Event {
+    Sample = 0,
+    Spawn = 1,
+    /// Written the tick after, at the last position seen.
+    Death = 2,
+    StateChange = 3,
+    PickUp = 4,
+    Deliver = 5,
+    /// Food dropped away from the nest.
+    Drop = 6,
+}
+
+impl Event {
+    fn name(self) -> &'static str {
+        match self {
+            Event::Sample => "",
+            Event::Spawn => "spawn",
+            Event::Death => "death",
+            Event::StateChange => "state",
+            Event::PickUp => "pick_up",
+            Event::Deliver => "deliver",
+            Event::Drop => "drop",
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+struct Record {
+    position: na::Point2<f32>,
+    velocity: na::Vector2<f32>,
+    angle: f32,
+    state: u32,
+    carried: u32,
+    delivered: u32,
+}
+
+/// Writes the paths, state changes and events of the followed ants.
+pub struct Recorder {
+    config: TrajectoryConfig,
+    writer: BufWriter<File>,
+    // What every followed ant looked like when last recorded.
+    last: HashMap<u64, Record>,
+}
+
+impl Recorder {
+    pub fn create(path: &str, config: &TrajectoryConfig) -> GameResult<Self> {
+        let file = File::create(path).map_err(|e| GameError::ConfigError(format!("could not create {}: {}", path, e)))?;
+        let mut recorder = Recorder {
+            config: config.clone(),
+            writer: BufWriter::new(file),
+            last: HashMap::new(),
+        };
+        let header: &[u8] = match config.format {
+            TrajectoryFormat::Csv => b"tick,ant,event,x,y,vx,vy,angle,state,carried\n",
+            TrajectoryFormat::Binary => b"ANTTRAJ1",
+        };
+        recorder.writer.write_all(header).map_err(error)?;
+        Ok(recorder)
+    }
+
+    fn follows(&self, world: &World, i: usize) -> bool {
+        (self.config.ants.is_empty() || self.config.ants.contains(&world.ants.id[i]))
+            && self.config.colony.is_none_or(|colony| colony == world.ants.colony[i])
+    }
+
+    pub fn record(&mut self, world: &World) -> GameResult {
+        let ants = &world.ants;
+        let tick = world.tick;
+        let sample = tick.is_multiple_of(self.config.every.max(1));
+        let mut seen = HashMap::with_capacity(self.last.len());
+
+        for i in 0..ants.len() {
+            if !self.follows(world, i) {
+                continue;
+            }
+            let id = ants.id[i];
+            let record = Record {
+                position: ants.position[i],
+                velocity: ants.velocity[i],
+                angle: ants.angle[i],
+                state: ants.state[i],
+                carried: ants.carried[i],
+                delivered: ants.delivered[i],
+            };
+
+            match self.last.get(&id).copied() {
+                None => self.write(tick, id, Event::Spawn, &record)?,
+                Some(last) => {
+                    if record.state != last.state {
+                        self.write(tick, id, Event::StateChange, &record)?;
+                    }
+                    if record.carried > last.carried {
+                        self.write(tick, id, Event::PickUp, &record)?;
+                    } else if record.delivered > last.delivered {
+                        self.write(tick, id, Event::Deliver, &record)?;
+                    } else if record.carried < last.carried {
+                        self.write(tick, id, Event::Drop, &record)?;
+                    }
+                },
+            }
+            if sample {
+                self.write(tick, id, Event::Sample, &record)?;
+            }
+            seen.insert(id, record);
+        }
+
+        let mut dead: Vec<(u64, Record)> = self.last.iter()
+            .filter(|(id, _)| !seen.contains_key(id))
+            .map(|(id, record)| (*id, *record))
+            .collect();
+        dead.sort_by_key(|(id, _)| *id);
+        for (id, record) in dead {
+            self.write(tick, id, Event::Death, &record)?;
+        }
+        self.last = seen;
+        self.writer.flush().map_err(error)
+    }
+
+    fn write(&mut self, tick: u64, id: u64, event: Event, record: &Record) -> GameResult {
+        match self.config.format {
+            TrajectoryFormat::Csv => writeln!(self.writer, "{},{},{},{},{},{},{},{},{},{}", tick, id, event.name(),
+                record.position.x, record.position.y, record.velocity.x, record.velocity.y, record.angle,
+                record.state, record.carried),
+            TrajectoryFormat::Binary => {
+                let mut bytes = Vec::with_capacity(31);
+                bytes.extend(&(tick as u32).to_le_bytes());
+                bytes.extend(&(id as u32).to_le_bytes());
+                bytes.push(event as u8);
+                for value in [record.position.x, record.position.y, record.velocity.x, record.velocity.y,
+                    record.angle].iter() {
+                    bytes.extend(&value.to_le_bytes());
+                }
+                bytes.push(record.state as u8);
+                bytes.push(record.carried.min(u8::MAX as u32) as u8);
+                self.writer.write_all(&bytes)
+            },
+        }
+        .map_err(error)
+    }
+}
+
+fn error(e: std::io::Error) -> GameError {
+    GameError::ConfigError(format!("could not write trajectories: {}", e))
+}
@@ -0,0 +1,158 @@
+use ggez::{GameError, GameResult};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rand_distr::{Distribution, Normal};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::evolution::{breed, check_mutation};
+use crate::world::World;
+
+/// A config parameter to tune, by its dotted path as in `Config::get_param`,
+/// and the range to search. `log` searches the range on a log scale, which
+/// suits rates like pheromone decay, and needs `min` above 0.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TunedParam {
+    pub path: String,
+    pub min: f64,
+    pub max: f64,
+    #[serde(default)]
+    pub log: bool,
+}
+
+/// Settings of the parameter search, see `tune`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TuningConfig {
+    pub params: Vec<TunedParam>,
+    pub population: usize,
+    pub generations: usize,
+    /// Best candidates carried over unchanged to the next generation.
+    pub elite: usize,
+    /// Candidates drawn per tournament when picking a parent.
+    pub tournament: usize,
+    /// Chance of every parameter to be mutated, and the deviation it is
+    /// mutated by as a fraction of its range.
+    pub mutation_rate: f32,
+    pub mutation_strength: f32,
+    /// Length of one evaluation run, and the seeds every candidate is run with.
+    pub ticks: u64,
+    pub seeds: Vec<u64>,
+}
+
+impl Default for TuningConfig {
+    fn default() -> Self {
+        TuningConfig {
+            params: Vec::new(),
+            population: 16,
+            generations: 10,
+            elite: 2,
+            tournament: 3,
+            mutation_rate: 0.3,
+            mutation_strength: 0.1,
+            ticks: 1800,
+            seeds: vec![1, 2],
+        }
+    }
+}
+
+impl TuningConfig {
+    /// Rejects settings the genetic algorithm cannot run with.
+    pub fn validate(&self) -> GameResult {
+        for param in self.params.iter() {
+            if !param.min.is_finite() || !param.max.is_finite() {
+                return Err(GameError::ConfigError(format!("the range of {} must be finite", param.path)));
+            }
+            if param.min > param.max {
+                return Err(GameError::ConfigError(format!("{}..{} is not a range for {}", param.min, param.max,
+                    param.path)));
+            }
+            if param.log && param.min <= 0.0 {
+                return Err(GameError::ConfigError(format!("{} is tuned on a log scale, so its min must be above 0",
+                    param.path)));
+            }
+        }
+        check_mutation("tuning", self.mutation_strength)
+    }
+}
+
+impl TunedParam {
+    // Genes live in [0, 1] and are spread over the range here.
+    fn value(&self, gene: f32) -> f64 {
+        let gene = gene as f64;
+        if self.log {
+            self.min * (self.max / self.min).powf(gene)
+        } else {
+            self.min + (self.max - self.min) * gene
+        }
+    }
+}
+
+/// `config` with the parameters set to the values the genes stand for.
+pub fn candidate(config: &Config, genes: &[f32]) -> GameResult<Config> {
+    let mut candidate = config.clone();
+    for (param, gene) in config.tuning.params.iter().zip(genes) {
+        candidate.set_param(&param.path, param.value(*gene))?;
+    }
+    Ok(candidate)
+}
+
+/// Food delivered by all colonies per tick, averaged over the seeds.
+pub fn fitness(config: &Config) -> GameResult<f32> {
+    let settings = &config.tuning;
+    let dt = config.dt.unwrap_or(1.0 / 60.0);
+    let scores = settings.seeds.iter()
+        .map(|&seed| {
+            let mut world = World::new(Config { seed: Some(seed), script: None, ..config.clone() })?;
+            world.initilize_positions();
+            for _ in 0..settings.ticks {
+                world.step(dt)?;
+            }
+            let delivered: u64 = world.colonies.iter().map(|colony| colony.food_delivered).sum();
+            Ok(delivered as f32 / settings.ticks.max(1) as f32)
+        })
+        .collect::<GameResult<Vec<f32>>>()?;
+    Ok(scores.iter().sum::<f32>() / settings.seeds.len().max(1) as f32)
+}
+
+/// Searches the `[tuning]` parameters with a genetic algorithm, evaluating
+/// every generation in parallel headless worlds. `report` gets the
+/// generation, the best config so far and the generation's best and mean
+/// fitness; the best config found is returned.
+pub fn tune(config: &Config, mut report: impl FnMut(usize, &Config, f32, f32)) -> GameResult<Config> {
+    let settings = &config.tuning;
+    if settings.params.is_empty() {
+        return Err(GameError::ConfigError("no parameters to tune, add some to [tuning]".to_string()));
+    }
+    settings.validate()?;
+    // Fails early on paths that do not exist.
+    candidate(config, &vec![0.5; settings.params.len()])?;
+
+    let mut rng = StdRng::seed_from_u64(config.seed.unwrap_or(0));
+    let mutation = Normal::new(0.0, settings.mutation_strength).unwrap();
+    let mut population: Vec<Vec<f32>> = (0..settings.population.max(1))
+        .map(|_| settings.params.iter().map(|_| rng.gen::<f32>()).collect())
+        .collect();
+
+    let mut best = (config.clone(), f32::MIN);
+    for generation in 0..settings.generations {
+        let candidates = population.iter()
+            .map(|genes| candidate(config, genes))
+            .collect::<GameResult<Vec<Config>>>()?;
+        let scores = candidates.par_iter().map(fitness).collect::<GameResult<Vec<f32>>>()?;
+
+        let top = (0..scores.len()).max_by(|a, b| scores[*a].total_cmp(&scores[*b])).unwrap();
+        if scores[top] > best.1 {
+            best = (candidates[top].clone(), scores[top]);
+        }
+        report(generation, &best.0, scores[top], scores.iter().sum::<f32>() / scores.len() as f32);
+
+        population = breed(&population, &scores, settings.elite, settings.tournament, &mut rng, |gene, rng| {
+            if rng.gen::<f32>() < settings.mutation_rate {
+                *gene = (*gene + mutation.sample(rng)).clamp(0.0, 1.0);
+            }
+        });
+    }
+    Ok(best.0)
+}
@@ -0,0 +1,632 @@
+use ggez::nalgebra as na;
+use ggez::{GameError, GameResult};
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use rayon::prelude::*;
+use std::cmp::min;
+
+use crate::ant::Ant;
+use crate::brain::{Decision, Mark, Perception, PheromoneReading, RETURN, SEARCH, TARGET};
+use crate::caste::{Caste, CasteConfig};
+use crate::colony::Colony;
+use crate::config::Config;
+use crate::food::Food;
+use crate::predator::Predator;
+use crate::profile::Profiler;
+use crate::replay::{Intervention, TimedIntervention};
+use crate::scenario::{Event, FoodSource, Scenario};
+use crate::sensor;
+use crate::spatial::SpatialHash;
+use crate::terrain::{Terrain, TerrainRegion};
+use crate::utils;
+use crate::{ANT_VISION, HEIGHT, TOTAL_FOOD, WIDTH};
+
+/// What an ant sensed and decided during the sensing phase. It is computed
+/// in parallel against a read-only world and applied afterwards.
+struct Interaction {
+    food_reached: bool,
+    remaining: usize,
+    food_candidate: Option<(u32, f32)>,
+    touching_home: bool,
+    decision: Decision,
+}
+
+enum Deposit {
+    Cell { colony: usize, layer: usize, x: usize, y: usize },
+    Disk { colony: usize, layer: usize, x: f32, y: f32, radius: f32 },
+}
+
+pub struct World {
+    pub config: Config,
+    pub ants: Ant,
+    pub colonies: Vec<Colony>,
+    pub food: Food,
+    pub predators: Predator,
+    pub terrain: Terrain,
+    pub scenario: Scenario,
+    /// Number of steps taken so far.
+    pub tick: u64,
+    pub seed: u64,
+    /// Interventions made so far, in order, for recording replays.
+    pub interventions: Vec<TimedIntervention>,
+    /// Times the phases of every tick while enabled.
+    pub profiler: Profiler,
+    ant_index: SpatialHash,
+    rng: StdRng,
+}
+
+impl World {
+    pub fn new(mut config: Config) -> GameResult<Self> {
+        if config.castes.is_empty() {
+            config.castes = CasteConfig::defaults();
+        }
+        // Fresh seeds stay within TOML's integer range so replays can store them.
+        let seed = config.seed.unwrap_or_else(|| StdRng::from_entropy().gen_range(0..=i64::MAX as u64));
+        let mut rng = StdRng::seed_from_u64(seed);
+        let colonies = config.colonies.iter()
+            .map(|colony| Colony::new(colony, &config, WIDTH as usize, HEIGHT as usize, rng.gen()))
+            .collect::<GameResult<Vec<_>>>()?;
+
+        Ok(World {
+            ants: Ant::new(WIDTH, HEIGHT),
+            colonies,
+            food: Food::new(WIDTH, HEIGHT, ANT_VISION, rng.gen()),
+            predators: Predator::new(&config.predators, WIDTH, HEIGHT, rng.gen()),
+            terrain: Terrain::new(&config.terrain, WIDTH as usize, HEIGHT as usize)?,
+            scenario: Scenario::new(&config.scenario),
+            tick: 0,
+            seed,
+            interventions: Vec::new(),
+            profiler: Profiler::default(),
+            ant_index: SpatialHash::new(WIDTH, HEIGHT, ANT_VISION),
+            rng,
+            config,
+        })
+    }
+
+    pub fn initilize_positions(&mut self) {
+        for c in 0..self.colonies.len() {
+            for _ in 0..self.config.colonies[c].ants {
+                self.spawn_ant(c);
+            }
+        }
+
+        let pellets = self.food.add_food(na::Point2::new(50.0,50.0),
+            na::Point2::new(WIDTH-50.0,100.0), TOTAL_FOOD as u32);
+        self.scenario.sources.push(FoodSource::new("initial", pellets.collect(), &[], 0.0));
+    }
+
+    /// Advances the world by `dt`. A scenario event that fails does not stop
+    /// the tick, its error is returned once the tick is done.
+    pub fn step(&mut self, dt: f32) -> GameResult {
+        let profiler = self.profiler.clone();
+        let _tick = profiler.span("tick");
+        let scenario = {
+            let _span = profiler.span("scenario");
+            self.run_scenario(dt)
+        };
+
+        {
+            let _span = profiler.span("pheromone decay");
+            for colony in self.colonies.iter_mut() {
+                colony.pheromones.update(&self.terrain);
+            }
+        }
+
+        let (mut interactions, deposits) = {
+            let _span = profiler.span("sensing");
+            self.ant_index.rebuild(self.ants.position.iter());
+            self.raise_alarms();
+            self.sense()
+        };
+        {
+            let _span = profiler.span("food search");
+            self.search_food(&mut interactions);
+        }
+        {
+            let _span = profiler.span("interactions");
+            self.resolve(&interactions);
+            self.apply_deposits(deposits);
+        }
+
+        let escaped = {
+            let _span = profiler.span("movement");
+            self.ants.update(dt, self.config.boundary, &self.config.energy, &self.terrain)
+        };
+
+        let _span = profiler.span("predators and population");
+        // The ants have moved since sensing, predators hunt them where they are now.
+        self.ant_index.rebuild(self.ants.position.iter());
+        let castes = &self.config.castes;
+        let ant_castes = &self.ants.caste;
+        let killed = self.predators.update(dt, &self.ants.position, &self.ant_index, self.config.boundary,
+            &self.terrain, |i| castes[ant_castes[i]].caste == Caste::Soldier);
+
+        // Starved, old, eaten and escaped ants die; escaped ones are replaced
+        // by a fresh ant at their nest. Removing from the back keeps indices valid.
+        let mask = |indices: &[usize]| {
+            let mut mask = vec![false; self.ants.len()];
+            for &i in indices {
+                mask[i] = true;
+            }
+            mask
+        };
+        let (escaped, killed) = (mask(&escaped), mask(&killed));
+        let mut dead: Vec<usize> = (0..self.ants.len())
+            .filter(|&i| self.ants.is_dead(i) || escaped[i] || killed[i])
+            .collect();
+        let mut replacements = Vec::new();
+        while let Some(i) = dead.pop() {
+            if escaped[i] {
+                replacements.push(self.ants.colony[i]);
+            }
+            if killed[i] {
+                // The colony marks the spot where one of its ants was eaten.
+                let colony = &mut self.colonies[self.ants.colony[i]];
+                let position = self.ants.position[i];
+                if let Some(layer) = colony.pheromones.ids.danger {
+                    colony.pheromones.layers[layer].deposit_disk(position.x, position.y,
+                        self.config.predators.mark_radius, self.config.deposit_strength);
+                }
+                colony.caste_stats[self.ants.caste[i]].killed += 1;
+            }
+            self.kill_ant(i);
+        }
+        for c in replacements {
+            self.spawn_ant(c);
+        }
+
+        self.reproduce(dt);
+        self.tick += 1;
+        scenario
+    }
+
+    /// Moves ants and predators caught under new walls out to the nearest
+    /// open ground. Ants buried too deep for that die.
+    fn clear_walls(&mut self) {
+        const SEARCH_RADIUS: usize = 100;
+        for i in (0..self.ants.len()).rev() {
+            if self.terrain.passable(&self.ants.position[i]) {
+                continue;
+            }
+            match self.terrain.nearest_passable(&self.ants.position[i], SEARCH_RADIUS) {
+                Some(position) => self.ants.position[i] = position,
+                None => self.kill_ant(i),
+            }
+        }
+        for position in self.predators.position.iter_mut() {
+            if !self.terrain.passable(position) {
+                if let Some(open) = self.terrain.nearest_passable(position, SEARCH_RADIUS) {
+                    *position = open;
+                }
+            }
+        }
+    }
+
+    fn kill_ant(&mut self, i: usize) {
+        if let Some(j) = self.ants.target_food_index[i] {
+            self.food.release(j);
+        }
+        self.colonies[self.ants.colony[i]].caste_stats[self.ants.caste[i]].deaths += 1;
+        self.ants.remove(i);
+    }
+
+    /// Fires the events due this tick and moves the food sources. Every due
+    /// event is applied even if an earlier one fails; the first error is kept.
+    fn run_scenario(&mut self, dt: f32) -> GameResult {
+        let mut result = Ok(());
+        for event in self.scenario.due(self.tick) {
+            if let Err(e) = self.apply_event(event) {
+                if result.is_ok() {
+                    result = Err(GameError::ConfigError(format!("scenario event at tick {} failed: {}", self.tick, e)));
+                }
+            }
+        }
+
+        let mut moved = false;
+        for source in self.scenario.sources.iter_mut() {
+            if let Some(offset) = source.advance(dt) {
+                for &j in source.pellets.iter() {
+                    self.food.move_by(j, offset);
+                }
+                moved = true;
+            }
+        }
+        if moved {
+            self.retarget_ants();
+        }
+        result
+    }
+
+    /// Applies a change from outside the simulation and logs it.
+    pub fn intervene(&mut self, intervention: Intervention) -> GameResult {
+        match &intervention {
+            Intervention::Event(event) => self.apply_event(event.clone())?,
+            Intervention::SetParam { path, value } => self.set_param(path, *value)?,
+        }
+        self.interventions.push(TimedIntervention { tick: self.tick, intervention });
+        Ok(())
+    }
+
+    /// Changes a config parameter while running, see `Config::set_param`.
+    /// Counts, positions and terrain only shape the initial world and are not
+    /// picked up afterwards.
+    pub fn set_param(&mut self, path: &str, value: f64) -> GameResult {
+        self.config.set_param(path, value)?;
+
+        for (colony, config) in self.colonies.iter_mut().zip(self.config.colonies.iter()) {
+            colony.reproduction = config.reproduction.clone();
+            colony.sensor = sensor::from_config(&config.sensor);
+            colony.brain.configure(&self.config);
+            for (layer, config) in colony.pheromones.layers.iter_mut().zip(self.config.pheromones.iter()) {
+                layer.decay = config.decay;
+                layer.diffusion = config.diffusion;
+            }
+        }
+        self.predators.config = self.config.predators.clone();
+        Ok(())
+    }
+
+    pub fn apply_event(&mut self, event: Event) -> GameResult {
+        match event {
+            Event::AddFood { name, x, y, width, height, amount, path, speed } => {
+                let pellets = self.food.add_food(na::Point2::new(x, y), na::Point2::new(x + width, y + height), amount);
+                self.scenario.sources.push(FoodSource::new(&name, pellets.collect(), &path, speed));
+            },
+            Event::RemoveFood { name } => {
+                if !self.scenario.sources.iter().any(|source| source.name == name) {
+                    return Err(GameError::ConfigError(format!("there is no food source {}", name)));
+                }
+                for source in self.scenario.sources.iter().filter(|source| source.name == name) {
+                    for &j in source.pellets.iter() {
+                        self.food.remove(j);
+                    }
+                }
+                self.scenario.sources.retain(|source| source.name != name);
+                self.retarget_ants();
+            },
+            Event::AddWall { x, y, width, height } => {
+                let region = TerrainRegion { kind: "wall".to_string(), x, y, width, height };
+                self.terrain.paint(&region)?;
+                self.clear_walls();
+            },
+            Event::RemoveWall { x, y, width, height } => {
+                let region = TerrainRegion { kind: self.terrain.background().to_string(), x, y, width, height };
+                self.terrain.paint(&region)?;
+            },
+            Event::AddNest(colony) => {
+                let c = self.colonies.len();
+                let seed = self.rng.gen();
+                self.colonies.push(Colony::new(&colony, &self.config, WIDTH as usize, HEIGHT as usize, seed)?);
+                for _ in 0..colony.ants {
+                    self.spawn_ant(c);
+                }
+                self.config.colonies.push(colony);
+            },
+            Event::RemoveNest { colony } => {
+                if colony >= self.colonies.len() {
+                    return Err(GameError::ConfigError(format!("there is no colony {}", colony)));
+                }
+                self.colonies[colony].active = false;
+                for i in (0..self.ants.len()).rev() {
+                    if self.ants.colony[i] == colony {
+                        self.kill_ant(i);
+                    }
+                }
+            },
+            Event::Rain { strength } => {
+                for colony in self.colonies.iter_mut() {
+                    colony.pheromones.wash(strength);
+                }
+            },
+        }
+        Ok(())
+    }
+
+    // Ants heading for a pellet follow it when it moves and go back to
+    // searching when it is gone.
+    fn retarget_ants(&mut self) {
+        for i in 0..self.ants.len() {
+            if let Some(j) = self.ants.target_food_index[i] {
+                match self.food.position[j as usize] {
+                    Some(position) => self.ants.target_food_position[i] = Some(position),
+                    None => {
+                        self.ants.target_food_index[i] = None;
+                        self.ants.target_food_position[i] = None;
+                        self.ants.state[i] = 0;
+                    },
+                }
+            }
+        }
+    }
+
+    fn reproduce(&mut self, dt: f32) {
+        let mut population = vec![0; self.colonies.len()];
+        let mut nurses = vec![0; self.colonies.len()];
+        for (c, caste) in self.ants.colony.iter().zip(self.ants.caste.iter()) {
+            population[*c] += 1;
+            if self.config.castes[*caste].caste == Caste::Nurse {
+                nurses[*c] += 1;
+            }
+        }
+
+        for (c, population) in population.into_iter().enumerate() {
+            for _ in 0..self.colonies[c].births(dt, population, nurses[c]) {
+                self.spawn_ant(c);
+            }
+        }
+    }
+
+    pub fn spawn_ant(&mut self, colony: usize) {
+        let (position, angle) = self.colonies[colony].home.generate_starting_position();
+        let caste = random_caste(&mut self.rng, &self.config);
+        let traits = self.config.castes[caste].sample(&mut self.rng, &self.config.energy);
+        self.ants.spawn(position, angle, colony, caste, traits, self.config.energy.max_energy);
+        self.colonies[colony].caste_stats[caste].births += 1;
+    }
+
+    // Ants inside a danger zone raise the alarm on their colony's trails.
+    fn raise_alarms(&mut self) {
+        let strength = self.config.deposit_strength;
+        for zone in self.config.danger_zones.iter() {
+            let center = na::Point2::new(zone.x, zone.y);
+            let ants = &self.ants;
+            let colonies = &mut self.colonies;
+            self.ant_index.query(&center, zone.radius, |i| {
+                let position = ants.position[i as usize];
+                if utils::distance(&center, &position) < zone.radius {
+                    let pheromones = &mut colonies[ants.colony[i as usize]].pheromones;
+                    pheromones.deposit(pheromones.ids.alarm, position.x as usize, position.y as usize, strength);
+                }
+            });
+        }
+    }
+
+    /// The parallel half of the tick: food lookup, pickup and home checks,
+    /// sensing and the brain's decision. Nothing is mutated here, deposits
+    /// are gathered into per-thread buffers and concatenated.
+    fn sense(&self) -> (Vec<Interaction>, Vec<Deposit>) {
+        let ants = &self.ants;
+        let colonies = &self.colonies;
+        let food = &self.food;
+        let predators = &self.predators;
+        let config = &self.config;
+        // Every ant gets its own random stream per tick, so the outcome does
+        // not depend on how the work is split between threads.
+        let tick_seed = self.seed ^ self.tick.wrapping_mul(0x9E37_79B9_7F4A_7C15);
+
+        (0..ants.len()).into_par_iter()
+            .fold(|| (Vec::new(), Vec::new()), |(mut interactions, mut deposits), i| {
+                let c = ants.colony[i];
+                let colony = &colonies[c];
+                let position = ants.position[i];
+                let caste = &config.castes[ants.caste[i]];
+
+                let food_reached = ants.food_acquired(i);
+                let remaining = if food_reached { food.count_unclaimed(&position, ants.vision[i]) } else { 0 };
+                let touching_home = colony.home.touching_home(position);
+                let threat = predators.nearest(&position, config.predators.detection_radius);
+
+                // Ant following pheromones algorithm
+
+                let pheromones: Vec<PheromoneReading> = colony.sensor.sense(position, ants.angle[i], &colony.pheromones)
+                    .into_iter()
+                    .map(|reading| reading.map(|(angle, score)| (na::Vector2::new(angle.cos(), angle.sin()), score)))
+                    .collect();
+
+                let mut rng = StdRng::seed_from_u64(tick_seed.wrapping_add(i as u64));
+                let perception = Perception {
+                    id: ants.id[i],
+                    position,
+                    heading: ants.desired_direction[i],
+                    state: ants.state[i],
+                    caste,
+                    wander_strength: ants.wander_strength[i],
+                    carried: ants.carried[i],
+                    hungry: ants.energy[i] < config.energy.hunger_threshold,
+                    exhausted: ants.exhausted[i],
+                    home: colony.home.position,
+                    at_home: touching_home,
+                    target_food: ants.target_food_position[i],
+                    food_reached,
+                    food_remaining: remaining,
+                    threat,
+                    layers: colony.pheromones.ids,
+                    pheromones: &pheromones,
+                    angle: ants.angle[i],
+                    field: &colony.pheromones,
+                    random: na::Vector2::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0)),
+                };
+                let decision = colony.brain.decide(&perception);
+
+                let approximate_y : usize = min(position.y.max(0.0) as usize, HEIGHT as usize - 1);
+                let approximate_x : usize = min(position.x.max(0.0) as usize, WIDTH as usize - 1);
+                for mark in decision.marks.iter() {
+                    deposits.push(match *mark {
+                        Mark::Cell(layer) => Deposit::Cell { colony: c, layer, x: approximate_x, y: approximate_y },
+                        Mark::Disk(layer, radius) => Deposit::Disk { colony: c, layer, x: position.x, y: position.y,
+                            radius },
+                    });
+                }
+
+                interactions.push(Interaction { food_reached, remaining, food_candidate: None, touching_home, decision });
+                (interactions, deposits)
+            })
+            .reduce(|| (Vec::new(), Vec::new()), |(mut interactions, mut deposits), (more, more_deposits)| {
+                interactions.extend(more);
+                deposits.extend(more_deposits);
+                (interactions, deposits)
+            })
+    }
+
+    /// Foraging ants still searching after sensing find the nearest pellet no
+    /// other ant is after, for `resolve` to hand out.
+    fn search_food(&self, interactions: &mut [Interaction]) {
+        let ants = &self.ants;
+        let food = &self.food;
+        let castes = &self.config.castes;
+        interactions.par_iter_mut().enumerate().for_each(|(i, interaction)| {
+            if interaction.decision.state == SEARCH && castes[ants.caste[i]].forages() {
+                let position = ants.position[i];
+                interaction.food_candidate = food.nearest_unclaimed(&position, ants.vision[i]).and_then(|j| {
+                    food.position[j as usize].map(|k| (j, utils::distance(&position, &k)))
+                });
+            }
+        });
+    }
+
+    /// The serial half of the tick. Food claims are settled deterministically:
+    /// a contested pellet goes to the closest ant, ties to the lowest index.
+    fn resolve(&mut self, interactions: &[Interaction]) {
+        let mut claims: Vec<(u32, f32, usize)> = interactions.iter().enumerate()
+            .filter_map(|(i, interaction)| interaction.food_candidate.map(|(j, d)| (j, d, i)))
+            .collect();
+        claims.sort_by(|a, b| a.0.cmp(&b.0)
+            .then(a.1.total_cmp(&b.1))
+            .then(a.2.cmp(&b.2)));
+        claims.dedup_by_key(|claim| claim.0);
+
+        for (i, interaction) in interactions.iter().enumerate() {
+            let decision = &interaction.decision;
+            self.ants.desired_direction[i] = decision.direction;
+            self.ants.state[i] = decision.state;
+
+            if interaction.food_reached && decision.pick_up {
+                self.ants.carried[i] += 1;
+                if let Some(j) = self.ants.target_food_index[i] {
+                    self.food.remove(j);
+                }
+                self.ants.target_food_position[i] = None;
+                self.ants.target_food_index[i] = None;
+                self.ants.exhausted[i] = interaction.remaining == 0;
+                if decision.state == RETURN {
+                    self.ants.set_antiparallel(i);
+                }
+            }
+
+            // A brain that stops targeting hands its pellet back.
+            if decision.state != TARGET {
+                if let Some(j) = self.ants.target_food_index[i].take() {
+                    self.food.release(j);
+                    self.ants.target_food_position[i] = None;
+                }
+            }
+        }
+
+        for (j, _, i) in claims {
+            if let Some(k) = self.food.position[j as usize] {
+                self.food.claim(j);
+                self.ants.set_food_target(i, k, j);
+            }
+        }
+
+        for (i, interaction) in interactions.iter().enumerate() {
+            if interaction.decision.drop {
+                let colony = &mut self.colonies[self.ants.colony[i]];
+                let carried = self.ants.carried[i];
+                if interaction.touching_home {
+                    self.ants.delivered[i] += carried;
+                    colony.food_store += carried as f32;
+                    colony.food_delivered += carried as u64;
+                    colony.caste_stats[self.ants.caste[i]].food_delivered += carried as u64;
+                    if carried > 0 {
+                        colony.trips += 1;
+                        colony.trip_distance += self.ants.trip[i] as f64;
+                    }
+                }
+                self.ants.carried[i] = 0;
+            }
+
+            if interaction.touching_home {
+                // Eat from the store until full or the store runs dry.
+                let colony = &mut self.colonies[self.ants.colony[i]];
+                let energy = &self.config.energy;
+                let wanted = (energy.max_energy - self.ants.energy[i]) / energy.energy_per_food;
+                let eaten = wanted.min(colony.food_store).max(0.0);
+                colony.food_store -= eaten;
+                self.ants.energy[i] += eaten * energy.energy_per_food;
+
+                self.ants.exhausted[i] = false;
+                self.ants.trip[i] = 0.0;
+                self.ants.set_antiparallel(i);
+            }
+        }
+    }
+
+    // Deposits keep the maximum concentration, so the order they are
+    // applied in does not matter.
+    fn apply_deposits(&mut self, deposits: Vec<Deposit>) {
+        let strength = self.config.deposit_strength;
+        for deposit in deposits {
+            match deposit {
+                Deposit::Cell { colony, layer, x, y } => {
+                    self.colonies[colony].pheromones.layers[layer].deposit(x, y, strength);
+                },
+                Deposit::Disk { colony, layer, x, y, radius } => {
+                    self.colonies[colony].pheromones.layers[layer].deposit_disk(x, y, radius, strength);
+                },
+            }
+        }
+    }
+}
+
+fn random_caste(rng: &mut StdRng, config: &Config) -> usize {
+    let total: f32 = config.castes.iter().map(|caste| caste.ratio.max(0.0)).sum();
+    if total <= 0.0 {
+        return 0;
+    }
+
+    let mut pick = rng.gen_range(0.0..total);
+    for (i, caste) in config.castes.iter().enumerate() {
+        pick -= caste.ratio.max(0.0);
+        if pick < 0.0 {
+            return i;
+        }
+    }
+    config.castes.len() - 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::brain::BrainConfig;
+
+    #[test]
+    fn failing_events_are_returned() {
+        let mut world = World::new(Config::default()).unwrap();
+        assert!(world.apply_event(Event::RemoveFood { name: "nowhere".to_string() }).is_err());
+        assert!(world.apply_event(Event::RemoveNest { colony: 9 }).is_err());
+        // Failed interventions are not recorded for replays.
+        assert!(world.intervene(Intervention::Event(Event::RemoveNest { colony: 9 })).is_err());
+        assert!(world.interventions.is_empty());
+    }
+
+    #[test]
+    fn brain_errors_are_returned() {
+        let mut config = Config::default();
+        config.colonies[0].brain = BrainConfig::Neural { network: "no/such/network.toml".to_string() };
+        assert!(World::new(config).is_err());
+    }
+
+    #[test]
+    fn steps_do_not_depend_on_the_thread_count() {
+        let run = |threads| {
+            let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+            pool.install(|| {
+                let mut config = Config { seed: Some(11), ..Config::default() };
+                config.colonies.truncate(1);
+                config.colonies[0].ants = 200;
+                let mut world = World::new(config).unwrap();
+                world.initilize_positions();
+                for _ in 0..5 {
+                    world.step(1.0 / 60.0).unwrap();
+                }
+                world
+            })
+        };
+        let (one, four) = (run(1), run(4));
+        assert_eq!(one.ants.id, four.ants.id);
+        assert_eq!(one.ants.position, four.ants.position);
+        assert_eq!(one.ants.state, four.ants.state);
+    }
+}